@@ -0,0 +1,113 @@
+//! Simple throughput benchmarks for the lexer, run with `cargo bench`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::single_long_line;
+use pilaris::dump::write_token_dump;
+use pilaris::lexer::{Lexer, LexerConfig, Token};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counts the allocations and reallocations, to compare preallocating.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn lex_all(code: &str) -> (usize, Duration) {
+    let mut lexer = Lexer::from_source("bench.plr", code);
+    let start = Instant::now();
+    let mut token_count = 0;
+    while lexer.get_token().unwrap() != Token::EOF {
+        token_count += 1;
+    }
+    (token_count, start.elapsed())
+}
+
+fn dump_all(code: &str) -> Duration {
+    let mut lexer = Lexer::from_source("bench.plr", code);
+    let mut sink = BufWriter::new(std::io::sink());
+    let start = Instant::now();
+    write_token_dump(&mut sink, &mut lexer).unwrap();
+    start.elapsed()
+}
+
+fn tokenize_all(code: &str, preallocate: bool) -> (usize, Duration) {
+    let mut lexer = LexerConfig::default()
+        .preallocate_tokens(preallocate)
+        .build("bench.plr", code);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let tokenized = lexer.tokenize_all();
+    let elapsed = start.elapsed();
+    assert!(tokenized.errors.is_empty());
+    (ALLOCATIONS.load(Ordering::Relaxed) - allocations, elapsed)
+}
+
+fn main() {
+    // Lexing time should grow linearly with the size of the line
+    let mut throughputs = Vec::new();
+    for size_mb in [1, 2, 5, 10] {
+        let code = single_long_line(size_mb * 1024 * 1024);
+        let (token_count, elapsed) = lex_all(&code);
+        let throughput = size_mb as f64 / elapsed.as_secs_f64();
+        println!(
+            "single line, {:>2}MB: {:>8} tokens in {:>10.3?} ({:.1} MB/s)",
+            size_mb, token_count, elapsed, throughput
+        );
+        throughputs.push(throughput);
+    }
+    // Loose enough for noise, a quadratic lexer would be 10 times slower
+    let (first, last) = (throughputs[0], throughputs[throughputs.len() - 1]);
+    assert!(
+        last > first / 3.0,
+        "Lexing a 10MB line is not linear, {:.1} MB/s against {:.1} MB/s for 1MB",
+        last,
+        first
+    );
+
+    // Dumping should not be much slower than lexing alone
+    for size_mb in [1, 10] {
+        let code = single_long_line(size_mb * 1024 * 1024);
+        let elapsed = dump_all(&code);
+        println!(
+            "dump single line, {:>2}MB: {:>10.3?} ({:.1} MB/s)",
+            size_mb,
+            elapsed,
+            size_mb as f64 / elapsed.as_secs_f64()
+        );
+    }
+
+    // Preallocating should save most of the reallocations of the vectors
+    for size_mb in [1, 10] {
+        let code = single_long_line(size_mb * 1024 * 1024);
+        for preallocate in [false, true] {
+            let (allocations, elapsed) = tokenize_all(&code, preallocate);
+            println!(
+                "tokenize_all, {:>2}MB, preallocate {:>5}: {:>8} allocations in {:>10.3?}",
+                size_mb, preallocate, allocations, elapsed
+            );
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Feeds arbitrary bytes to the lexer, which must never panic, only return
+//! tokens or errors until the end of the input.
+//!
+//! Needs a nightly toolchain and `cargo install cargo-fuzz`, then from the
+//! repository root:
+//!
+//! ```sh
+//! cargo +nightly fuzz run lexer fuzz/corpus/lexer
+//! ```
+//!
+//! The seed corpus in `fuzz/corpus/lexer` is a copy of `input_examples`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pilaris::lexer::{Lexer, Token};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut lexer) = Lexer::from_bytes("<fuzz>", data) else {
+        return;
+    };
+    // Stops at the first error
+    loop {
+        match lexer.get_token() {
+            Ok(Token::EOF) | Err(_) => break,
+            Ok(_) => {
+                lexer.token_str();
+                lexer.token_start_column();
+            }
+        }
+    }
+    // Recovers from errors
+    if let Ok(mut lexer) = Lexer::from_bytes("<fuzz>", data) {
+        lexer.tokenize_all();
+    }
+});
@@ -18,7 +18,11 @@ pub enum Token {
 enum ParseTokenState {
     NewLine,
     PopGroup,
-    Identifier { ident_range: Range<usize> },
+    /// The start of a line inside brackets, when they are free-form
+    Continuation,
+    Identifier {
+        ident_range: Range<usize>,
+    },
 }
 
 pub struct Lexer {
@@ -30,6 +34,16 @@ pub struct Lexer {
     columnno: usize,
     group_level: Vec<usize>,
     parse_token_state: ParseTokenState,
+    /// How many `[` are open
+    bracket_depth: usize,
+    /// Whether identation inside brackets is ignored
+    free_form_brackets: bool,
+    /// Where each open `{` is, as its line and column, innermost last
+    open_braces: Vec<(usize, usize)>,
+    /// Whether blocks can also be written between `{` and `}`
+    brace_blocks: bool,
+    /// How many columns each group must be idented by, if enforced
+    indent_step: Option<usize>,
 }
 
 impl Lexer {
@@ -48,9 +62,38 @@ impl Lexer {
             columnno: 1,
             group_level: vec![],
             parse_token_state: ParseTokenState::NewLine,
+            bracket_depth: 0,
+            free_form_brackets: false,
+            open_braces: vec![],
+            brace_blocks: false,
+            indent_step: None,
         })
     }
 
+    /// Makes the lines inside `[` and `]` free-form, like Python's implicit
+    /// line joining. Their identation is ignored, so they never begin or end
+    /// groups, which lets a list span many lines.
+    pub fn free_form_brackets(mut self, enabled: bool) -> Self {
+        self.free_form_brackets = enabled;
+        self
+    }
+
+    /// Lets blocks be written between `{` and `}` instead of identing them.
+    /// Both give the same `GroupBegin` and `GroupEnd`, and the identation
+    /// inside braces is ignored, so each block can pick its style.
+    pub fn brace_blocks(mut self, enabled: bool) -> Self {
+        self.brace_blocks = enabled;
+        self
+    }
+
+    /// Makes each group be idented by exactly `step` columns more than the
+    /// one containing it, anything else is a
+    /// [`LexicError::InconsistentIndentStep`]. Not enforced by default.
+    pub fn indent_step(mut self, step: usize) -> Self {
+        self.indent_step = Some(step);
+        self
+    }
+
     pub fn get_token(&mut self) -> Result<Token, LexicError> {
         use ParseTokenState::*;
         loop {
@@ -66,6 +109,18 @@ impl Lexer {
                                     self.group_level.last().cloned().unwrap_or(1);
                                 match self.columnno.cmp(&last_group_ident) {
                                     Ordering::Greater => {
+                                        let found = self.columnno - last_group_ident;
+                                        match self.indent_step {
+                                            Some(expected) if found != expected => {
+                                                return Err(LexicError::InconsistentIndentStep {
+                                                    file: self.input_filepath.clone(),
+                                                    line: self.lineno,
+                                                    expected,
+                                                    found,
+                                                });
+                                            }
+                                            _ => (),
+                                        }
                                         self.group_level.push(self.columnno);
                                         self.parse_token_state = Identifier {
                                             ident_range: self.parse_head..self.parse_head,
@@ -91,13 +146,14 @@ impl Lexer {
                         match self.columnno.cmp(&last_group_ident) {
                             Ordering::Less => {
                                 self.parse_token_state = PopGroup;
-                                continue;
+                                break Ok(Token::GroupEnd);
                             }
                             Ordering::Greater => {
                                 return Err(LexicError::UnexpectedIdentationLevel {
                                     line: self.lineno,
                                     column: self.columnno,
                                     file: self.input_filepath.clone(),
+                                    context: self.line_context(),
                                 })
                             }
                             _ => (),
@@ -105,7 +161,18 @@ impl Lexer {
                         self.parse_token_state = Identifier {
                             ident_range: self.parse_head..self.parse_head,
                         };
+                        break Ok(Token::GroupEnd);
                     }
+                    Continuation => match current_char {
+                        ' ' => (),
+                        '\n' => self.newline(),
+                        _ => {
+                            self.parse_token_state = Identifier {
+                                ident_range: self.parse_head..self.parse_head,
+                            };
+                            continue;
+                        }
+                    },
                     Identifier { ident_range } => match current_char {
                         c if (c.is_alphabetic()
                             || (c.is_alphanumeric() && self.parse_head != ident_range.start)) =>
@@ -114,11 +181,52 @@ impl Lexer {
                                 ident_range: ident_range.start..(self.parse_head + 1),
                             };
                         }
+                        '{' | '}' if self.brace_blocks && ident_range.is_empty() => {
+                            let brace = if current_char == '{' {
+                                self.open_braces.push((self.lineno, self.columnno));
+                                Token::GroupBegin
+                            } else if self.open_braces.pop().is_some() {
+                                Token::GroupEnd
+                            } else {
+                                return Err(LexicError::UnmatchedBrace {
+                                    file: self.input_filepath.clone(),
+                                    line: self.lineno,
+                                    column: self.columnno,
+                                });
+                            };
+                            self.head_forward(current_char);
+                            self.parse_token_state = Identifier {
+                                ident_range: self.parse_head..self.parse_head,
+                            };
+                            return Ok(brace);
+                        }
+                        // The identifier first, the brace is lexed next
+                        '{' | '}' if self.brace_blocks => {
+                            self.last_token_range = ident_range.start..ident_range.end;
+                            self.parse_token_state = Identifier {
+                                ident_range: self.parse_head..self.parse_head,
+                            };
+                            return Ok(Token::Identifier);
+                        }
+                        '\n' if self.free_form_brackets && self.bracket_depth > 0
+                            || !self.open_braces.is_empty() =>
+                        {
+                            self.last_token_range = ident_range.start..ident_range.end;
+                            self.newline();
+                            self.head_forward('\n');
+                            self.parse_token_state = Continuation;
+                            return Ok(Token::Identifier);
+                        }
                         '\n' => {
                             self.newline();
                             self.parse_token_state = NewLine;
                         }
                         other => {
+                            match other {
+                                '[' => self.bracket_depth += 1,
+                                ']' => self.bracket_depth = self.bracket_depth.saturating_sub(1),
+                                _ => (),
+                            }
                             self.last_token_range = ident_range.start..ident_range.end;
                             self.head_forward(other);
                             self.parse_token_state = Identifier {
@@ -129,12 +237,31 @@ impl Lexer {
                     },
                 } // End of match parse_token_state
                 self.head_forward(current_char);
+            } else if let Some(&(line, column)) = self.open_braces.last() {
+                break Err(LexicError::UnclosedBrace {
+                    file: self.input_filepath.clone(),
+                    line,
+                    column,
+                });
+            } else if self.group_level.pop().is_some() {
+                break Ok(Token::GroupEnd);
             } else {
                 break Ok(Token::EOF);
             }
         } // End of loop
     } // End of fn get_token
 
+    /// The first characters of the rest of the current line, to show where
+    /// an error is.
+    fn line_context(&self) -> String {
+        const CONTEXT_LENGTH: usize = 16;
+        self.code[self.parse_head..]
+            .chars()
+            .take_while(|&c| c != '\n')
+            .take(CONTEXT_LENGTH)
+            .collect()
+    }
+
     fn newline(&mut self) {
         self.lineno += 1;
         self.columnno = 0;
@@ -163,6 +290,30 @@ pub enum LexicError {
         file: PathBuf,
         line: usize,
         column: usize,
+        /// The start of the line with the unexpected identation
+        context: String,
+    },
+    /// A `}` with no open `{`, only with brace blocks
+    UnmatchedBrace {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    /// A `{` still open at the end of the file, reported where it opened
+    UnclosedBrace {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    /// A group idented by other than the step set with
+    /// [`Lexer::indent_step`]
+    InconsistentIndentStep {
+        file: PathBuf,
+        line: usize,
+        /// The columns each group must be idented by
+        expected: usize,
+        /// The columns this group is idented by
+        found: usize,
     },
 }
 
@@ -182,26 +333,186 @@ impl Display for LexicError {
             } => {
                 write!(
                     f,
-                    "{}:{}: Unexpected character '{}' at column {}",
+                    "{}:{}:{}: Unexpected character '{}'",
                     file.display(),
                     line,
-                    c,
-                    column
+                    column,
+                    c
                 )
             }
             UnexpectedIdentationLevel {
                 file,
                 line,
-                column: _,
+                column,
+                context,
             } => {
                 write!(
                     f,
-                    "{}:{}: Unexpected identation level at line {}",
+                    "{}:{}:{}: Unexpected identation level, near '{}'",
+                    file.display(),
+                    line,
+                    column,
+                    context,
+                )
+            }
+            UnmatchedBrace { file, line, column } => {
+                write!(
+                    f,
+                    "{}:{}:{}: Unmatched '}}', there is no open '{{'",
                     file.display(),
                     line,
+                    column
+                )
+            }
+            UnclosedBrace { file, line, column } => {
+                write!(
+                    f,
+                    "{}:{}:{}: Unclosed '{{', expected a '}}' before the end of the file",
+                    file.display(),
+                    line,
+                    column
+                )
+            }
+            InconsistentIndentStep {
+                file,
+                line,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "{}:{}: Inconsistent identation step, expected {} columns, found {}",
+                    file.display(),
                     line,
+                    expected,
+                    found
                 )
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identation_error_context() {
+        let mut lexer = Lexer::new("input_examples/bad_identation.plr").unwrap();
+        let error = loop {
+            match lexer.get_token() {
+                Ok(Token::EOF) => panic!("No identation error"),
+                Ok(_) => (),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(
+            &error,
+            LexicError::UnexpectedIdentationLevel { line: 3, context, .. }
+                if context == "misplaced and a "
+        ));
+        assert_eq!(
+            error.to_string(),
+            "input_examples/bad_identation.plr:3:3: \
+             Unexpected identation level, near 'misplaced and a '"
+        );
+    }
+
+    #[test]
+    fn free_form_brackets() {
+        fn tokens(free_form: bool) -> Result<Vec<(Token, String)>, LexicError> {
+            let mut lexer = Lexer::new("input_examples/bracket_list.plr")
+                .unwrap()
+                .free_form_brackets(free_form);
+            let mut tokens = Vec::new();
+            loop {
+                match lexer.get_token()? {
+                    Token::EOF => break Ok(tokens),
+                    token => tokens.push((token, lexer.token_str().to_owned())),
+                }
+            }
+        }
+
+        let is_group =
+            |(token, _): &(Token, String)| matches!(token, Token::GroupBegin | Token::GroupEnd);
+        let free_form = tokens(true).unwrap();
+        assert!(!free_form.iter().any(is_group));
+        for item in ["one", "two", "three"] {
+            assert!(free_form.contains(&(Token::Identifier, item.to_owned())));
+        }
+        // Otherwise the list has an unexpected identation level
+        assert!(matches!(
+            tokens(false),
+            Err(LexicError::UnexpectedIdentationLevel { line: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn brace_blocks() {
+        fn groups(path: &str) -> Result<Vec<Token>, LexicError> {
+            let mut lexer = Lexer::new(path).unwrap().brace_blocks(true);
+            let mut groups = Vec::new();
+            loop {
+                match lexer.get_token()? {
+                    Token::EOF => break Ok(groups),
+                    token @ (Token::GroupBegin | Token::GroupEnd) => groups.push(token),
+                    _ => (),
+                }
+            }
+        }
+
+        let indented = groups("input_examples/blocks_indented.plr").unwrap();
+        assert_eq!(
+            indented,
+            [
+                Token::GroupBegin,
+                Token::GroupBegin,
+                Token::GroupEnd,
+                Token::GroupEnd
+            ]
+        );
+        assert_eq!(
+            groups("input_examples/blocks_braced.plr").unwrap(),
+            indented
+        );
+        assert_eq!(groups("input_examples/blocks_mixed.plr").unwrap(), indented);
+
+        let error = groups("input_examples/unmatched_brace.plr").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "input_examples/unmatched_brace.plr:2:11: Unmatched '}', there is no open '{'"
+        );
+        let error = groups("input_examples/unclosed_brace.plr").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "input_examples/unclosed_brace.plr:1:7: \
+             Unclosed '{', expected a '}' before the end of the file"
+        );
+    }
+
+    #[test]
+    fn indent_step() {
+        fn lex_all(path: &str) -> Result<(), LexicError> {
+            let mut lexer = Lexer::new(path).unwrap().indent_step(4);
+            while lexer.get_token()? != Token::EOF {}
+            Ok(())
+        }
+
+        assert!(lex_all("input_examples/blocks_indented.plr").is_ok());
+        let error = lex_all("input_examples/indent_step_uneven.plr").unwrap_err();
+        assert!(matches!(
+            error,
+            LexicError::InconsistentIndentStep {
+                line: 3,
+                expected: 4,
+                found: 2,
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "input_examples/indent_step_uneven.plr:3: \
+             Inconsistent identation step, expected 4 columns, found 2"
+        );
+    }
+}
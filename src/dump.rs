@@ -0,0 +1,65 @@
+//! Writing tokens in the plain text format of `pilarisc --tokens`
+
+use crate::lexer::{Lexer, LexicError, Token};
+use std::io::{self, Write};
+
+/// Writes every token until the end of the input, one per line, as its kind,
+/// its quoted text and the column it starts at. Everything is written
+/// straight to `w`, so wrap it in a [`io::BufWriter`] when it is slow to
+/// write to, like the standard output.
+///
+/// Stops at the first lexic error, returned as an [`io::ErrorKind::InvalidData`]
+/// error wrapping the [`LexicError`]. Use [`lexic_error`] to get it back.
+pub fn write_token_dump(w: &mut impl Write, lexer: &mut Lexer) -> io::Result<()> {
+    loop {
+        let tok = lexer
+            .get_token()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writeln!(
+            w,
+            "{:?} \"{}\", starts at col: {}",
+            tok,
+            lexer.token_str(),
+            lexer.token_start_column()
+        )?;
+        if tok == Token::EOF {
+            break Ok(());
+        }
+    }
+}
+
+/// The lexic error that made [`write_token_dump`] fail, if it was not an
+/// error writing.
+pub fn lexic_error(error: &io::Error) -> Option<&LexicError> {
+    error.get_ref()?.downcast_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_as_old_format() {
+        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        let mut dump = Vec::new();
+        write_token_dump(&mut dump, &mut lexer).unwrap();
+        let expected =
+            std::fs::read_to_string("util_files/test_data/lexer_output/simple1.plr.txt").unwrap();
+        assert_eq!(
+            String::from_utf8(dump).unwrap().trim_end(),
+            expected.trim_end()
+        );
+    }
+
+    #[test]
+    fn lexic_errors() {
+        let mut lexer = Lexer::from_source("dump.plr", "a @");
+        let mut dump = Vec::new();
+        let error = write_token_dump(&mut dump, &mut lexer).unwrap_err();
+        assert!(matches!(
+            lexic_error(&error),
+            Some(LexicError::UnexpectedCharacter { c: '@', .. })
+        ));
+        assert_eq!(dump, b"Identifier \"a\", starts at col: 0\n");
+    }
+}
@@ -1,12 +1,150 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+/// How the tokens are dumped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenFormat {
+    /// One token per line, with its text and starting column.
+    Plain,
+    /// Aligned `kind | line | col | lexeme` columns, for cleaner diffs.
+    Columns,
+}
+
 pub struct Arguments {
-    pub source: String,
+    /// The source file, always present unless on REPL mode or explaining an
+    /// error code.
+    pub source: Option<String>,
+    /// Only checks the source for lexical errors, without dumping the tokens.
+    pub check: bool,
+    /// Reads and lexes lines from the standard input interactively.
+    pub repl: bool,
+    /// Dumps the tokens of the source, without any logging.
+    pub tokens: bool,
+    /// How the tokens are dumped.
+    pub token_format: TokenFormat,
+    /// Where to write the tokens as JSON, instead of dumping them.
+    pub emit_tokens: Option<String>,
+    /// How the errors are printed.
+    pub error_format: ErrorFormat,
+    /// How many errors are reported before giving up, when checking.
+    pub max_errors: usize,
+    /// Whether `#if FLAG` and `#endif` lines are directives rather than
+    /// comments. Implied by defining any flag.
+    pub directives: bool,
+    /// The flags defined for `#if` directives.
+    pub defines: Vec<String>,
+    /// Reports how long lexing the whole source takes.
+    pub time: bool,
+    /// Checks the source again whenever it changes, until interrupted.
+    pub watch: bool,
+    /// The error code to explain, instead of lexing anything.
+    pub explain: Option<String>,
 }
 
 impl Arguments {
     /// # Exits
     /// This function will stop the program execution if the arguments cannot be parsed.
     pub fn from_args() -> Self {
-        let source = std::env::args().nth(1).unwrap();
-        Arguments { source }
+        let mut source = None;
+        let mut check = false;
+        let mut repl = false;
+        let mut tokens = false;
+        let mut token_format = None;
+        let mut emit_tokens = None;
+        let mut directives = false;
+        let mut defines = Vec::new();
+        let mut time = false;
+        let mut watch = false;
+        let mut explain = None;
+        let mut error_format = ErrorFormat::Human;
+        let mut max_errors = pilaris::lexer::LexerConfig::DEFAULT_MAX_ERRORS;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--check" => check = true,
+                "--repl" => repl = true,
+                "--tokens" => tokens = true,
+                "--time" => time = true,
+                "--watch" => watch = true,
+                "--directives" => directives = true,
+                "--format" => {
+                    token_format = match args.next().as_deref() {
+                        Some("plain") => Some(TokenFormat::Plain),
+                        Some("columns") => Some(TokenFormat::Columns),
+                        _ => Self::exit_with_usage("--format expects 'plain' or 'columns'"),
+                    }
+                }
+                "--error-format" => {
+                    error_format = match args.next().as_deref() {
+                        Some("human") => ErrorFormat::Human,
+                        Some("json") => ErrorFormat::Json,
+                        _ => Self::exit_with_usage("--error-format expects 'human' or 'json'"),
+                    }
+                }
+                "--emit" => {
+                    emit_tokens = match args.next().as_deref().map(|emit| emit.split_once('=')) {
+                        Some(Some(("tokens", path))) if !path.is_empty() => Some(path.to_owned()),
+                        _ => Self::exit_with_usage("--emit expects 'tokens=<file>'"),
+                    }
+                }
+                "--explain" => match args.next() {
+                    Some(code) => explain = Some(code),
+                    None => Self::exit_with_usage("--explain expects an error code"),
+                },
+                "-D" => match args.next() {
+                    Some(flag) => defines.push(flag),
+                    None => Self::exit_with_usage("-D expects a flag"),
+                },
+                "--max-errors" => {
+                    max_errors = match args.next().map(|n| n.parse()) {
+                        Some(Ok(n)) => n,
+                        _ => Self::exit_with_usage("--max-errors expects a number"),
+                    }
+                }
+                flag if flag.starts_with("--") => {
+                    Self::exit_with_usage(&format!("Unknown option '{}'", flag))
+                }
+                _ if source.is_some() => Self::exit_with_usage("Too many source files"),
+                _ => source = Some(arg),
+            }
+        }
+        if source.is_none() && !repl && explain.is_none() {
+            Self::exit_with_usage("Missing source file");
+        }
+        if token_format.is_some() && !tokens {
+            Self::exit_with_usage("--format only applies to --tokens");
+        }
+        Arguments {
+            source,
+            check,
+            repl,
+            tokens,
+            token_format: token_format.unwrap_or(TokenFormat::Plain),
+            emit_tokens,
+            error_format,
+            max_errors,
+            directives: directives || !defines.is_empty(),
+            defines,
+            time,
+            watch,
+            explain,
+        }
+    }
+
+    fn exit_with_usage(message: &str) -> ! {
+        eprintln!("{}", message);
+        eprintln!(
+            "Usage: pilarisc [--check] [--max-errors N] [--error-format human|json] [--directives] [-D FLAG]... [--time] <source>"
+        );
+        eprintln!("       pilarisc --tokens [--format plain|columns] [--time] <source>");
+        eprintln!("       pilarisc --emit tokens=<file> <source>");
+        eprintln!("       pilarisc --watch [--directives] [-D FLAG]... <source>");
+        eprintln!("       pilarisc --repl");
+        eprintln!("       pilarisc --explain CODE");
+        eprintln!("--directives makes '#if FLAG' and '#endif' lines directives instead of comments, -D implies it");
+        std::process::exit(2);
     }
 }
@@ -1,34 +1,287 @@
 mod clargs;
 mod logger;
 
-use pilaris::lexer::Token;
+use clargs::{ErrorFormat, TokenFormat};
+use pilaris::dump;
+use pilaris::lexer::{sort_errors, Lexer, LexerConfig, LexicError, Token};
+use std::io::{BufRead, BufWriter, Write};
+use std::time::Instant;
 
 fn main() {
     logger::PilarisLogger::init(log::Level::Debug);
-    log::info!("Log enabled");
 
     let arguments = clargs::Arguments::from_args();
+    if let Some(code) = &arguments.explain {
+        match LexicError::explain(code) {
+            Some(explanation) => print!("{}", explanation),
+            None => {
+                eprintln!("Unknown error code '{}'", code);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+    if arguments.check
+        || arguments.repl
+        || arguments.tokens
+        || arguments.emit_tokens.is_some()
+        || arguments.time
+        || arguments.watch
+    {
+        // Keep the output clean, checking should print nothing on success and
+        // the REPL and token dumps are meant for the user
+        logger::PilarisLogger::set_enabled(false);
+    }
+    log::info!("Log enabled");
+
+    if arguments.repl {
+        repl();
+        return;
+    }
+
+    // Only the REPL and explaining run without a source file
+    let source = arguments.source.unwrap();
+    let config = arguments.defines.iter().fold(
+        LexerConfig::default()
+            .max_errors(arguments.max_errors)
+            .directives(arguments.directives),
+        |config, flag| config.define(flag.as_str()),
+    );
+    if let Err(e) = config.validate() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    if arguments.watch {
+        #[cfg(feature = "watch")]
+        watch(&source, &config, arguments.error_format);
+        #[cfg(not(feature = "watch"))]
+        {
+            eprintln!("--watch needs pilarisc built with the 'watch' feature");
+            std::process::exit(2);
+        }
+    }
+    let mut lexer = match config.open(&source) {
+        Ok(lexer) => lexer,
+        Err(e) => {
+            report_errors(&[e], arguments.error_format);
+            std::process::exit(1);
+        }
+    };
+
+    // Timed on its own pass, so dumping the tokens is not part of it
+    let timed_errors = arguments.time.then(|| {
+        // Safe to unwrap, the start of the source is always a valid offset
+        time_lexing(lexer.clone_at(0).unwrap())
+    });
+
+    let mut errors = if arguments.check {
+        lexer.tokenize_all().errors
+    } else if let (Some(errors), false, None) =
+        (timed_errors, arguments.tokens, &arguments.emit_tokens)
+    {
+        // Only timing, the errors were already found
+        errors
+    } else if let Some(path) = &arguments.emit_tokens {
+        emit_tokens(&mut lexer, path).err().into_iter().collect()
+    } else {
+        let dumped = match arguments.token_format {
+            TokenFormat::Plain => dump_tokens(&mut lexer),
+            TokenFormat::Columns => dump_token_columns(&mut lexer),
+        };
+        dumped.err().into_iter().collect()
+    };
+    sort_errors(&mut errors);
+    report_errors(&errors, arguments.error_format);
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Lexes the whole source, printing to the standard error how long it took
+/// and how many tokens per second that is. Returns the errors found.
+fn time_lexing(mut lexer: Lexer) -> Vec<LexicError> {
+    let start = Instant::now();
+    let tokenized = lexer.tokenize_all();
+    let elapsed = start.elapsed();
+    let token_count = tokenized.tokens.len();
+    eprintln!(
+        "Lexed {} tokens in {:.3?} ({:.0} tokens/s)",
+        token_count,
+        elapsed,
+        token_count as f64 / elapsed.as_secs_f64()
+    );
+    tokenized.errors
+}
+
+/// Checks the source again whenever it changes, printing the errors each
+/// time. Saves close together are taken as one. Never returns, only exits
+/// if the source can't be watched.
+#[cfg(feature = "watch")]
+fn watch(source: &str, config: &LexerConfig, format: ErrorFormat) -> ! {
+    use notify::{RecursiveMode, Watcher};
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    let path = Path::new(source);
+    let (sender, events) = mpsc::channel();
+    // The directory rather than the file, editors often save by replacing it
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let watched = notify::recommended_watcher(sender).and_then(|mut watcher| {
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    // Dropping the watcher would stop the events
+    let _watcher = match watched {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Could not watch {}: {}", source, e);
+            std::process::exit(1);
+        }
+    };
+    let is_change = |event: &notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            (event.kind.is_modify() || event.kind.is_create())
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == path.file_name())
+        }
+        Err(_) => false,
+    };
+
+    loop {
+        let mut errors = match config.clone().open(path) {
+            Ok(mut lexer) => lexer.tokenize_all().errors,
+            Err(e) => vec![e],
+        };
+        sort_errors(&mut errors);
+        report_errors(&errors, format);
+        eprintln!("Waiting for changes to {}", source);
+
+        // Safe to unwrap, the watcher keeps the sender alive
+        while !is_change(&events.recv().unwrap()) {}
+        while events.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+/// Prints the errors to the standard error. In JSON format the errors are
+/// always printed as an array, even if there are none.
+fn report_errors(errors: &[LexicError], format: ErrorFormat) {
+    match format {
+        ErrorFormat::Human => {
+            for e in errors {
+                eprintln!("{}", e);
+            }
+        }
+        ErrorFormat::Json => {
+            let values: Vec<_> = errors.iter().map(LexicError::to_json_value).collect();
+            eprintln!("{}", serde_json::Value::Array(values));
+        }
+    }
+}
 
-    let mut lexer = pilaris::lexer::Lexer::new(arguments.source).unwrap();
+/// Lexes each line from the standard input until EOF or `:quit`.
+fn repl() {
+    const PROMPT: &str = "> ";
+    const QUIT_COMMAND: &str = ":quit";
 
-    // while let Ok(tok) = lexer.get_token() {
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
     loop {
-        match lexer.get_token() {
-            Ok(tok) => {
-                println!(
-                    "{:?} \"{}\", starts at col: {}",
-                    tok,
-                    lexer.token_str(),
-                    lexer.token_start_column()
-                );
-                if tok == Token::EOF {
-                    break;
+        print!("{}", PROMPT);
+        std::io::stdout().flush().ok();
+
+        match lines.next() {
+            Some(Ok(line)) if line.trim() == QUIT_COMMAND => break,
+            Some(Ok(line)) => {
+                if let Err(e) = dump_tokens(&mut Lexer::from_source("<repl>", line)) {
+                    eprintln!("{}", e);
                 }
             }
-            Err(e) => {
+            Some(Err(e)) => {
                 eprintln!("{}", e);
                 break;
             }
+            None => {
+                println!();
+                break;
+            }
+        }
+    }
+}
+
+/// Prints every token until the end of the input, stopping at the first error.
+fn dump_tokens(lexer: &mut Lexer) -> Result<(), LexicError> {
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+    let result = dump::write_token_dump(&mut stdout, lexer).and_then(|_| stdout.flush());
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => match dump::lexic_error(&e) {
+            Some(lexic_error) => {
+                // Whatever was dumped before the error should still show
+                stdout.flush().ok();
+                Err(lexic_error.clone())
+            }
+            None => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// Prints every token as aligned `kind | line | col | lexeme` columns, with
+/// the position counting from 1 and the lexeme quoted and escaped, stopping at
+/// the first error.
+fn dump_token_columns(lexer: &mut Lexer) -> Result<(), LexicError> {
+    println!("{:<18} | {:>5} | {:>4} | lexeme", "kind", "line", "col");
+    loop {
+        let tok = lexer.get_token()?;
+        let (line, column) = lexer.line_col_at(lexer.token_span().start);
+        // Debug does not pad by itself
+        let kind = format!("{:?}", tok);
+        println!(
+            "{:<18} | {:>5} | {:>4} | {:?}",
+            kind,
+            line,
+            column,
+            lexer.token_str()
+        );
+        if tok == Token::EOF {
+            break Ok(());
         }
     }
 }
+
+/// Writes every token to a file as a JSON array, with the kind, text and
+/// position of each. Nothing is written if lexing fails.
+fn emit_tokens(lexer: &mut Lexer, path: &str) -> Result<(), LexicError> {
+    let mut tokens = Vec::new();
+    loop {
+        let tok = lexer.get_token()?;
+        if tok == Token::EOF {
+            break;
+        }
+        let span = lexer.token_span();
+        let (line, column) = lexer.line_col_at(span.start);
+        tokens.push(serde_json::json!({
+            "kind": tok.kind_name(),
+            "lexeme": lexer.token_str(),
+            "line": line,
+            "column": column,
+            "start": span.start,
+            "end": span.end,
+        }));
+    }
+    if let Err(e) = std::fs::write(path, serde_json::Value::Array(tokens).to_string()) {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -1,244 +1,5895 @@
+//! The lexer turns source code into a stream of [`Token`]s, one at a time
+//! through [`Lexer::get_token`].
+//!
+//! Lexing is linear in the size of the input: every character is looked at a
+//! constant number of times and no token requires rescanning the source, so
+//! even a single multi-megabyte line is lexed in linear time.
+//!
+//! Nothing in the lexer is recursive. Open groups, strings with
+//! interpolations and `#if` directives are kept on explicit stacks, so the
+//! nesting depth is only limited by memory, never by the call stack.
+
 use std::{
+    borrow::Cow,
+    cell::OnceCell,
+    collections::{HashMap, HashSet, VecDeque},
     io,
     ops::Range,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub enum Token {
     Identifier,
+    /// A lone `_`, used as a wildcard
+    Underscore,
+    /// An identifier reserved by the language, see [`KEYWORDS`]
     Keyword,
+    /// A literal written as a name, as `true` or `null`, see
+    /// [`LexerConfig::named_literals`]
+    NamedLiteral,
     Colon,
+    /// A `:` right before a newline, opening a block. Only emitted when
+    /// enabled with [`LexerConfig::block_colons`].
+    BlockColon,
+    /// The `::` path separator, as in `std::io`
+    ColonColon,
+    StringLiteral,
+    IntegerLiteral,
+    FloatLiteral,
+    /// A number immediately followed by `%`, as in `50%`
+    PercentLiteral,
+    /// The `%` operator
+    Percent,
+    /// The `+` operator. This and the other arithmetic operators are only
+    /// emitted when enabled with [`LexerConfig::arithmetic_operators`].
+    Plus,
+    /// The `-` operator
+    Minus,
+    /// The `*` operator
+    Star,
+    /// The `/` operator
+    Slash,
+    /// A `?`, for optional or error propagation syntax
+    Question,
+    /// The `=` of assignments
+    Equals,
+    /// A `.`, for member access
+    Dot,
+    /// A `..`, for ranges
+    DotDot,
+    /// A `...`, for variadic or inclusive range syntax
+    Ellipsis,
+    /// A `|`, for bitwise or pattern alternation
+    Pipe,
+    /// A `&`, for bitwise and
+    Ampersand,
+    /// A `#`, as in the `#[attribute]` syntax. Only when comments don't start
+    /// with `#`, see [`LexerConfig::comment_style`].
+    Hash,
+    /// A `$`, as in shell-like `$variable` syntax. Outside of strings only,
+    /// see [`LexerConfig::string_interpolation`].
+    Dollar,
+    /// A `^`, for bitwise xor
+    Caret,
+    /// A `~`, for bitwise not
+    Tilde,
+    /// Text between backticks, as in `` `code` ``. Only emitted when enabled
+    /// with [`LexerConfig::inline_code`].
+    InlineCode,
+    /// The opening quote and text of a string with interpolations, up to the
+    /// first `${`
+    StringStart,
+    /// The text of a string with interpolations between a `}` and a `${`
+    StringFragment,
+    /// The last text and the closing quote of a string with interpolations
+    StringEnd,
+    /// The `${` starting an interpolation inside a string
+    InterpolationStart,
+    /// The `}` ending an interpolation
+    InterpolationEnd,
+    /// An empty `()`, for the unit value or an empty argument list. Only
+    /// emitted when enabled with [`LexerConfig::unit_token`].
+    Unit,
+    /// The `(` opening a group, or any other opening delimiter configured
+    /// with [`LexerConfig::group_delimiters`]
     ParensOpen,
+    /// The `)` closing a group, or the closing delimiter matching the opening
+    /// one
     ParensClose,
     GroupBegin,
     GroupEnd,
+    Comment,
+    DocComment,
+    /// A `\` and the line break right after it, joining the next line to
+    /// this one. Trivia like comments, only emitted when preserving trivia.
+    LineContinuation,
     EOF,
 }
 
+impl Token {
+    /// A stable snake_case name for the kind of token, as `parens_open` for
+    /// `ParensOpen`. Meant for output read by other tools, which should not
+    /// depend on the `Debug` format.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Token::Identifier => "identifier",
+            Token::Underscore => "underscore",
+            Token::Keyword => "keyword",
+            Token::NamedLiteral => "named_literal",
+            Token::Colon => "colon",
+            Token::BlockColon => "block_colon",
+            Token::ColonColon => "colon_colon",
+            Token::StringLiteral => "string_literal",
+            Token::IntegerLiteral => "integer_literal",
+            Token::FloatLiteral => "float_literal",
+            Token::PercentLiteral => "percent_literal",
+            Token::Percent => "percent",
+            Token::Plus => "plus",
+            Token::Minus => "minus",
+            Token::Star => "star",
+            Token::Slash => "slash",
+            Token::Question => "question",
+            Token::Equals => "equals",
+            Token::Dot => "dot",
+            Token::DotDot => "dot_dot",
+            Token::Ellipsis => "ellipsis",
+            Token::Pipe => "pipe",
+            Token::Ampersand => "ampersand",
+            Token::Hash => "hash",
+            Token::Dollar => "dollar",
+            Token::Caret => "caret",
+            Token::Tilde => "tilde",
+            Token::InlineCode => "inline_code",
+            Token::StringStart => "string_start",
+            Token::StringFragment => "string_fragment",
+            Token::StringEnd => "string_end",
+            Token::InterpolationStart => "interpolation_start",
+            Token::InterpolationEnd => "interpolation_end",
+            Token::Unit => "unit",
+            Token::ParensOpen => "parens_open",
+            Token::ParensClose => "parens_close",
+            Token::GroupBegin => "group_begin",
+            Token::GroupEnd => "group_end",
+            Token::Comment => "comment",
+            Token::DocComment => "doc_comment",
+            Token::LineContinuation => "line_continuation",
+            Token::EOF => "eof",
+        }
+    }
+
+    /// The category of the token when highlighting code. Exhaustive, so
+    /// every new token must pick one.
+    pub fn highlight_group(&self) -> HighlightGroup {
+        use Token::*;
+        match self {
+            Identifier | Underscore => HighlightGroup::Identifier,
+            Keyword | NamedLiteral => HighlightGroup::Keyword,
+            StringLiteral | StringStart | StringFragment | StringEnd | InlineCode => {
+                HighlightGroup::String
+            }
+            IntegerLiteral | FloatLiteral | PercentLiteral => HighlightGroup::Number,
+            Comment | DocComment => HighlightGroup::Comment,
+            Percent | Plus | Minus | Star | Slash | Question | Equals | DotDot | Ellipsis
+            | Pipe | Ampersand | Caret | Tilde => HighlightGroup::Operator,
+            Colon | BlockColon | ColonColon | Dot | Hash | Dollar | InterpolationStart
+            | InterpolationEnd | Unit | ParensOpen | ParensClose | LineContinuation => {
+                HighlightGroup::Punctuation
+            }
+            GroupBegin | GroupEnd | EOF => HighlightGroup::None,
+        }
+    }
+}
+
+/// The categories of tokens for syntax highlighting, see
+/// [`Token::highlight_group`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum HighlightGroup {
+    Identifier,
+    Keyword,
+    /// String literals and their parts, and inline code
+    String,
+    Number,
+    Comment,
+    Operator,
+    /// Delimiters and separators, as parens and `::`
+    Punctuation,
+    /// Tokens with no text to highlight, as `GroupBegin` and `EOF`
+    None,
+}
+
+/// A range of bytes in the source. Spans are ordered by their start, then by
+/// their end.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    #[inline]
+    pub fn range(self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    #[inline]
+    pub fn len(self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Span {
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+/// The result of everything that can fail while lexing, from creating the
+/// lexer to reading tokens.
+pub type LexResult<T> = Result<T, LexicError>;
+
+/// The identifiers reserved by the language, lexed as `Token::Keyword`.
+pub const KEYWORDS: &[&str] = &[
+    "fn", "let", "if", "else", "match", "while", "for", "in", "return",
+];
+
+/// Whether the text is one of the [`KEYWORDS`], that is, whether the lexer
+/// would take it as a `Token::Keyword`.
+#[inline]
+pub fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(&s)
+}
+
+/// Lexes the whole source, counting how many times each kind of token
+/// appears. `EOF` is not counted.
+pub fn histogram(source: &str) -> LexResult<HashMap<Token, usize>> {
+    let mut lexer = Lexer::from_source("<source>", source);
+    let mut counts = HashMap::new();
+    loop {
+        match lexer.get_token()? {
+            Token::EOF => break Ok(counts),
+            tok => *counts.entry(tok).or_insert(0) += 1,
+        }
+    }
+}
+
+/// Puts the errors in source order, by line, column and offset, and removes
+/// the repeated ones, which recovery may report more than once for the same
+/// place. Errors at the same position are told apart by their message.
+pub fn sort_errors(errors: &mut Vec<LexicError>) {
+    errors.sort_by(|a, b| {
+        a.cmp_position(b)
+            .then_with(|| a.offset().cmp(&b.offset()))
+            .then_with(|| a.to_string().cmp(&b.to_string()))
+    });
+    errors.dedup_by(|a, b| {
+        a.position() == b.position() && a.offset() == b.offset() && a.to_string() == b.to_string()
+    });
+}
+
+/// The character sequence that introduces a line comment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CommentStyle {
+    /// `# comment`, with `## doc comment`
+    #[default]
+    Hash,
+    /// `// comment`, with `/// doc comment`
+    DoubleSlash,
+    /// `-- comment`, with `--- doc comment`
+    DoubleDash,
+}
+
+impl CommentStyle {
+    pub fn introducer(self) -> &'static str {
+        match self {
+            CommentStyle::Hash => "#",
+            CommentStyle::DoubleSlash => "//",
+            CommentStyle::DoubleDash => "--",
+        }
+    }
+
+    pub fn doc_marker(self) -> &'static str {
+        match self {
+            CommentStyle::Hash => "##",
+            CommentStyle::DoubleSlash => "///",
+            CommentStyle::DoubleDash => "---",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum State {
     Looking,
-    AccIdent { range: Range<usize> },
+    AccIdent {
+        range: Range<usize>,
+    },
+    AccComment {
+        range: Range<usize>,
+    },
+    AccInlineCode {
+        range: Range<usize>,
+        start_line: usize,
+        start_column: usize,
+    },
+    AccNumber {
+        range: Range<usize>,
+        is_float: bool,
+    },
+    /// The type suffix of a number, as in `42i32`
+    AccNumberSuffix {
+        range: Range<usize>,
+        is_float: bool,
+    },
+    AccString {
+        range: Range<usize>,
+        escaping: bool,
+        start_line: usize,
+        start_column: usize,
+        /// Whether this is the rest of a string after an interpolation
+        continued: bool,
+    },
+    /// The `${` of an interpolation is next.
+    InterpolationStart,
+    /// A `(` was just returned, a `GroupBegin` follows it.
+    OpeningGroup,
+    /// A `GroupEnd` was just returned, the `)` comes next.
+    ClosingGroup,
+    /// An error was just returned with nothing to skip when recovering, the
+    /// next token is lexed as from `Looking`.
+    PastError,
+    /// A string too long was just returned as an error, the rest of it is
+    /// skipped before the next token.
+    SkippingString {
+        escaping: bool,
+    },
+}
+
+/// The source code being lexed. Cloning it shares the same buffer, it is
+/// only copied when edited.
+#[derive(Clone)]
+enum Source {
+    Owned(Arc<String>),
+    /// Borrowed for the whole program, as code compiled into the binary
+    Static(&'static str),
+    /// A memory map, already checked to be valid UTF-8
+    #[cfg(feature = "mmap")]
+    Mapped(Arc<memmap2::Mmap>),
+}
+
+impl Source {
+    /// The source as an editable string, copying a memory map, a static
+    /// string or a shared buffer out first.
+    fn to_mut(&mut self) -> &mut String {
+        if let Source::Static(code) = self {
+            *self = Source::Owned(Arc::new((*code).to_owned()));
+        }
+        #[cfg(feature = "mmap")]
+        if let Source::Mapped(map) = self {
+            *self = Source::Owned(Arc::new(map_as_str(map).to_owned()));
+        }
+        match self {
+            Source::Owned(code) => Arc::make_mut(code),
+            Source::Static(_) => unreachable!(),
+            #[cfg(feature = "mmap")]
+            Source::Mapped(_) => unreachable!(),
+        }
+    }
+}
+
+impl std::ops::Deref for Source {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Source::Owned(code) => code,
+            Source::Static(code) => code,
+            #[cfg(feature = "mmap")]
+            Source::Mapped(map) => map_as_str(map),
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[inline]
+fn map_as_str(map: &memmap2::Mmap) -> &str {
+    // Safety: the map is validated as UTF-8 before being used as a source
+    unsafe { std::str::from_utf8_unchecked(map) }
 }
 
 pub struct Lexer {
     input_filepath: PathBuf,
-    code: String,
+    code: Source,
     current_line: usize,
     current_column: usize,
     input_head: usize,
     state: State,
     token_range: Range<usize>,
+    current_token: Option<Token>,
+    /// The last token returned other than EOF, kept across errors
+    last_token: Option<Token>,
+    /// Warnings found so far, in the order they were found
+    warnings: Vec<LexicWarning>,
+    /// Errors recovered from by [`Lexer::get_token_recovering`], not taken
+    /// yet
+    errors: Vec<LexicError>,
+    config: LexerConfig,
+    /// The closing delimiter of each open group, innermost last
+    open_groups: Vec<char>,
+    /// The `#if` directives not yet closed, innermost last, with whether their
+    /// lines are lexed and their line, column and offset
+    conditions: Vec<(bool, usize, usize, usize)>,
+    /// Where each string with interpolations being lexed starts, as its line,
+    /// column and offset, innermost last
+    interpolations: Vec<(usize, usize, usize)>,
+    /// Column where the current token starts
+    token_column: usize,
+    /// Whether only whitespace was found since the last newline
+    at_line_start: bool,
+    token_starts_line: bool,
+    /// Byte offset where each line starts. Computed when first needed and
+    /// cleared whenever the source is edited, see [`Lexer::line_offsets`].
+    line_offsets: OnceCell<Vec<usize>>,
+    /// Tokens lexed by `peek_nth` but not yet returned by `get_token`
+    lookahead: VecDeque<Lookahead>,
+    /// An error found along with the token just lexed, returned right after
+    /// it by the next `lex_token`
+    pending_error: Option<LexicError>,
+    /// Entered while lexing, so everything logged is tied to the file
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    /// How many tokens were lexed, logged at the end of the input
+    #[cfg(feature = "tracing")]
+    token_count: usize,
 }
 
-impl Lexer {
-    const IDENT_BREAKERS: &'static [char] = &[' ', '\n', '(', ')', ':'];
-    const WHITESPACE: &'static [char] = &[' ', '\n'];
+/// A token lexed ahead of time, along with what is needed to make it the
+/// current token later.
+#[derive(Clone)]
+struct Lookahead {
+    result: LexResult<Token>,
+    range: Range<usize>,
+    column: usize,
+    starts_line: bool,
+}
 
-    /// Creates a new lexer for a source file
-    #[inline]
-    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
-        Self::_new(path.as_ref())
+/// Where a lexer was, taken by [`Lexer::checkpoint`] to go back to it later
+/// with [`Lexer::restore`].
+#[derive(Clone)]
+pub struct Checkpoint {
+    input_head: usize,
+    current_line: usize,
+    current_column: usize,
+    state: State,
+    token_range: Range<usize>,
+    current_token: Option<Token>,
+    last_token: Option<Token>,
+    warning_count: usize,
+    open_groups: Vec<char>,
+    conditions: Vec<(bool, usize, usize, usize)>,
+    interpolations: Vec<(usize, usize, usize)>,
+    token_column: usize,
+    at_line_start: bool,
+    token_starts_line: bool,
+    lookahead: VecDeque<Lookahead>,
+    pending_error: Option<LexicError>,
+}
+
+/// The options of a [`Lexer`]. Each setter takes and returns the config, so
+/// they can be chained before building the lexer:
+///
+/// ```
+/// use pilaris::lexer::{CommentStyle, LexerConfig};
+///
+/// let lexer = LexerConfig::default()
+///     .comment_style(CommentStyle::DoubleSlash)
+///     .tab_width(8)
+///     .build("example.plr", "code // comment");
+/// ```
+#[derive(Clone, Debug)]
+pub struct LexerConfig {
+    preserve_trivia: bool,
+    emit_group_markers: bool,
+    comment_style: CommentStyle,
+    block_colons: bool,
+    tab_width: usize,
+    max_errors: usize,
+    string_interpolation: bool,
+    inline_code: bool,
+    directives: bool,
+    defined_flags: HashSet<String>,
+    case_insensitive_keywords: bool,
+    case_insensitive_identifiers: bool,
+    keywords: bool,
+    named_literals: HashSet<String>,
+    arithmetic_operators: bool,
+    adjacent_string_errors: bool,
+    max_string_len: Option<usize>,
+    char_ranges: bool,
+    preallocate_tokens: bool,
+    group_delimiters: Vec<(char, char)>,
+    unit_token: bool,
+    document_separator: String,
+    /// Line and column of the first character, counting from zero
+    initial_position: (usize, usize),
+}
+
+impl Default for LexerConfig {
+    fn default() -> Self {
+        Self {
+            preserve_trivia: false,
+            emit_group_markers: false,
+            comment_style: CommentStyle::default(),
+            block_colons: false,
+            tab_width: Self::DEFAULT_TAB_WIDTH,
+            max_errors: Self::DEFAULT_MAX_ERRORS,
+            string_interpolation: false,
+            inline_code: false,
+            directives: false,
+            defined_flags: HashSet::new(),
+            case_insensitive_keywords: false,
+            case_insensitive_identifiers: false,
+            keywords: true,
+            named_literals: HashSet::new(),
+            arithmetic_operators: false,
+            adjacent_string_errors: false,
+            max_string_len: None,
+            char_ranges: false,
+            preallocate_tokens: false,
+            group_delimiters: vec![('(', ')')],
+            unit_token: false,
+            document_separator: "---".to_owned(),
+            initial_position: (0, 0),
+        }
     }
+}
+
+/// A combination of [`LexerConfig`] options that makes no sense together.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigError {
+    /// Error recovery would give up before reporting any error.
+    ZeroMaxErrors,
+    /// A group delimiter that already means something else, like `"` or a
+    /// letter.
+    ReservedGroupDelimiter(char),
+}
 
-    fn _new(path: &Path) -> io::Result<Self> {
-        std::fs::read_to_string(path).map(|code| {
-            let input_filepath = path.to_path_buf();
-            Self {
-                code,
-                input_filepath,
-                current_line: 0,
-                current_column: 0,
-                input_head: 0,
-                state: State::Looking,
-                token_range: 0..0,
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::ZeroMaxErrors => write!(
+                f,
+                "max_errors is 0, recovery would stop without reporting any error"
+            ),
+            ConfigError::ReservedGroupDelimiter(c) => {
+                write!(
+                    f,
+                    "'{}' already means something else, it can't delimit groups",
+                    c
+                )
             }
-        })
+        }
     }
+}
 
-    pub fn get_token(&mut self) -> Result<Token, LexicError> {
-        self.token_range = 0..0;
-        loop {
-            let current_c = self.getc();
+impl Error for ConfigError {}
 
-            match self.state.clone() {
-                State::Looking => match current_c {
-                    Some(c) if c.is_alphabetic() || c == '_' => {
-                        self.state = State::AccIdent {
-                            range: self.input_head..(self.input_head + c.len_utf8()),
-                        };
-                        self.advance();
-                    }
-                    Some('(') => {
-                        self.token_range =
-                            self.input_head..(self.input_head + '('.len_utf8());
-                        self.state = State::Looking;
-                        self.advance();
-                        break Ok(Token::ParensOpen);
-                    }
-                    Some(')') => {
-                        self.token_range =
-                            self.input_head..(self.input_head + ')'.len_utf8());
-                        self.state = State::Looking;
-                        self.advance();
-                        break Ok(Token::ParensClose);
-                    }
-                    Some(':') => {
-                        self.token_range =
-                            self.input_head..(self.input_head + ':'.len_utf8());
-                        self.state = State::Looking;
-                        self.advance();
-                        break Ok(Token::Colon);
-                    }
-                    Some(c) if Self::WHITESPACE.contains(&c) => {
-                        self.advance();
-                    }
-                    Some(c) => break Err(self.err_unexpected_char(c)),
-                    None => break Ok(Token::EOF),
-                },
-                State::AccIdent { range } => match current_c {
-                    Some(c) if c.is_alphanumeric() || c == '_' => {
-                        self.advance();
-                        self.state = State::AccIdent {
-                            range: range.start..self.input_head,
-                        };
-                    }
-                    // Either an ident breaker or None (as None would unwrap or true)
-                    _ if current_c.map(|c| Self::IDENT_BREAKERS.contains(&c)).unwrap_or(true) => {
-                        self.token_range = range;
-                        self.state = State::Looking;
-                        break Ok(Token::Identifier);
-                    }
-                    // I'm sure None would be matched by the above arm, but
-                    // rustc can't tell so we'll unwrap it here, it is Some
-                    // unexpected character for sure. The '\0' is here for
-                    // funsies.
-                    _ => break Err(self.err_unexpected_char(current_c.unwrap_or('\0'))),
-                },
-            }
+impl LexerConfig {
+    /// The closing delimiter for a group opened with `c`, if `c` opens one.
+    fn opens_group(&self, c: char) -> Option<char> {
+        self.group_delimiters
+            .iter()
+            .find(|&&(open, _)| open == c)
+            .map(|&(_, close)| close)
+    }
+
+    fn closes_group(&self, c: char) -> bool {
+        self.group_delimiters.iter().any(|&(_, close)| close == c)
+    }
+}
+
+impl LexerConfig {
+    pub const DEFAULT_MAX_ERRORS: usize = 100;
+    pub const DEFAULT_TAB_WIDTH: usize = 4;
+    /// Roughly how many bytes of source there are per token, to guess how
+    /// many tokens a source has, see [`LexerConfig::preallocate_tokens`].
+    pub const BYTES_PER_TOKEN_HINT: usize = 4;
+
+    /// A preset for arithmetic expressions, as in a calculator: integer and
+    /// float literals, parens and the arithmetic operators, with no
+    /// keywords. The same as
+    ///
+    /// ```
+    /// # use pilaris::lexer::LexerConfig;
+    /// LexerConfig::default()
+    ///     .arithmetic_operators(true)
+    ///     .keywords(false);
+    /// ```
+    pub fn calculator() -> Self {
+        Self::default().arithmetic_operators(true).keywords(false)
+    }
+
+    /// Checks that the options make sense together, without lexing anything.
+    /// Every lexer is built from a valid config, so tools can call this first
+    /// to report a bad config instead of panicking.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_errors == 0 {
+            return Err(ConfigError::ZeroMaxErrors);
+        }
+        let reserved = |c: char| {
+            c.is_alphanumeric()
+                || c.is_whitespace()
+                || Lexer::IDENT_BREAKERS.contains(&c) && !"()}".contains(c)
+                || c == '}' && self.string_interpolation
+                || self.arithmetic_operators && Lexer::ARITHMETIC_OPERATORS.contains(&c)
+                || ['_', '#', '/', '@', '$'].contains(&c)
+        };
+        if let Some(&c) = self
+            .group_delimiters
+            .iter()
+            .flat_map(|(open, close)| [open, close])
+            .find(|&&c| reserved(c))
+        {
+            return Err(ConfigError::ReservedGroupDelimiter(c));
         }
+        Ok(())
     }
 
+    /// Creates a lexer for source code already in memory with this config.
+    /// The `name` is used in place of the file path when reporting errors.
+    ///
+    /// # Panics
+    /// This and the other constructors panic if the config is invalid, see
+    /// [`LexerConfig::validate`].
     #[inline]
-    pub fn getc(&self) -> Option<char> {
-        self.code[self.input_head..].chars().next()
+    pub fn build(self, name: impl Into<PathBuf>, code: impl Into<String>) -> Lexer {
+        Lexer::_with_config(name.into(), Source::Owned(Arc::new(code.into())), self)
     }
 
-    pub fn advance(&mut self) {
-        let c = self.getc();
-        let char_length = c.map(char::len_utf8).unwrap_or(0);
-        self.input_head += char_length;
-        match c {
-            Some('\n') => {
-                self.current_line += 1;
-                self.current_column = 0;
-            }
-            Some(_) => self.current_column += 1,
-            None => (),
-        }
+    /// Creates a lexer for a static source with this config, without
+    /// copying it, see [`Lexer::from_static`].
+    #[inline]
+    pub fn build_static(self, name: impl Into<PathBuf>, code: &'static str) -> Lexer {
+        Lexer::_with_config(name.into(), Source::Static(code), self)
     }
 
+    /// Reads a source file and creates a lexer for it with this config.
     #[inline]
-    pub fn input_filepath(&self) -> &Path {
-        &self.input_filepath
+    pub fn open(self, path: impl AsRef<Path>) -> LexResult<Lexer> {
+        self._open(path.as_ref())
     }
 
+    /// Creates a lexer for raw bytes, which must be valid UTF-8.
     #[inline]
-    pub fn token_str(&self) -> &str {
-        &self.code[self.token_range.clone()]
+    pub fn build_from_bytes(self, name: impl Into<PathBuf>, bytes: &[u8]) -> LexResult<Lexer> {
+        self._build_from_bytes(name.into(), bytes)
     }
 
-    pub fn token_start_column(&self) -> usize {
-        self.current_column - self.token_str().chars().count()
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(file = %name.display())))]
+    fn _build_from_bytes(self, name: PathBuf, bytes: &[u8]) -> LexResult<Lexer> {
+        let code = self.validate_utf8(&name, bytes)?.to_owned();
+        Ok(Lexer::_with_config(
+            name,
+            Source::Owned(Arc::new(code)),
+            self,
+        ))
     }
 
-    fn err_unexpected_char(&self, c: char) -> LexicError {
-        LexicError::UnexpectedCharacter {
-            c,
-            file: self.input_filepath.clone(),
-            line: self.current_line + 1,
-            column: self.current_column + 1,
+    fn validate_utf8<'a>(&self, name: &Path, bytes: &'a [u8]) -> LexResult<&'a str> {
+        match std::str::from_utf8(bytes) {
+            Ok(code) => Ok(code),
+            Err(e) => {
+                let offset = e.valid_up_to();
+                // Safe to unwrap, this part was already validated
+                let valid = std::str::from_utf8(&bytes[..offset]).unwrap();
+                let line_start = valid.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                let column = valid[line_start..].chars().fold(0, |column, c| match c {
+                    '\t' => column + self.tab_width - column % self.tab_width,
+                    _ => column + 1,
+                });
+                Err(LexicError::InvalidUtf8 {
+                    file: name.to_path_buf(),
+                    line: valid.matches('\n').count() + 1,
+                    column: column + 1,
+                    offset,
+                })
+            }
         }
     }
-}
 
-#[derive(Debug)]
-pub enum LexicError {
-    UnexpectedCharacter {
-        c: char,
-        file: PathBuf,
-        line: usize,
-        column: usize,
-    },
-    UnexpectedIdentationLevel {
-        file: PathBuf,
-        line: usize,
-        column: usize,
-    },
-}
+    /// Reads the whole source from a reader, as a pipe or a socket, and
+    /// creates a lexer for it with this config. The source is decoded as it
+    /// is read, characters split between two reads are fine, only bytes that
+    /// are not valid UTF-8 fail with `LexicError::InvalidUtf8`.
+    #[inline]
+    pub fn from_reader(
+        self,
+        name: impl Into<PathBuf>,
+        mut reader: impl io::Read,
+    ) -> LexResult<Lexer> {
+        self._from_reader(name.into(), &mut reader)
+    }
 
-use std::error::Error;
-use std::fmt::{Display, Formatter};
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(file = %name.display())))]
+    fn _from_reader(self, name: PathBuf, reader: &mut dyn io::Read) -> LexResult<Lexer> {
+        const CHUNK_SIZE: usize = 8 * 1024;
+        let mut code = String::new();
+        // Bytes read but not decoded yet, the start of a split character
+        let mut pending = Vec::new();
+        let mut chunk = [0; CHUNK_SIZE];
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(LexicError::io(&name, e)),
+            };
+            pending.extend_from_slice(&chunk[..read]);
+            let valid = match std::str::from_utf8(&pending) {
+                Ok(decoded) => decoded.len(),
+                // Cut in the middle of a character, the rest comes next
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => break,
+            };
+            // Safe to unwrap, this part was already validated
+            code.push_str(std::str::from_utf8(&pending[..valid]).unwrap());
+            pending.drain(..valid);
+        }
+        if !pending.is_empty() {
+            // Either invalid or a character cut by the end of the input,
+            // validating everything read to find where
+            let bytes = [code.as_bytes(), &pending].concat();
+            return Err(self.validate_utf8(&name, &bytes).unwrap_err());
+        }
+        Ok(Lexer::_with_config(
+            name,
+            Source::Owned(Arc::new(code)),
+            self,
+        ))
+    }
 
-impl Error for LexicError {}
-impl Display for LexicError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        use LexicError::*;
-        match self {
-            UnexpectedCharacter {
-                c,
-                file,
-                line,
-                column,
-            } => {
-                write!(
-                    f,
-                    "{}:{}: Unexpected character '{}' at column {}",
-                    file.display(),
-                    line,
-                    c,
-                    column
-                )
-            }
-            UnexpectedIdentationLevel {
-                file,
-                line,
-                column: _,
-            } => {
-                write!(
-                    f,
-                    "{}:{}: Unexpected identation level at line {}",
-                    file.display(),
-                    line,
-                    line,
-                )
-            }
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(file = %path.display())))]
+    fn _open(self, path: &Path) -> LexResult<Lexer> {
+        let bytes = std::fs::read(path).map_err(|e| LexicError::io(path, e))?;
+        match String::from_utf8(bytes) {
+            Ok(code) => Ok(Lexer::_with_config(
+                path.to_path_buf(),
+                Source::Owned(Arc::new(code)),
+                self,
+            )),
+            // Validating again only to find where the bad byte is
+            Err(e) => Err(self.validate_utf8(path, e.as_bytes()).unwrap_err()),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Memory maps a source file and creates a lexer for it with this config,
+    /// see [`Lexer::from_mmap`].
+    #[cfg(feature = "mmap")]
+    #[inline]
+    pub fn open_mmap(self, path: impl AsRef<Path>) -> LexResult<Lexer> {
+        self._open_mmap(path.as_ref())
+    }
 
-    #[test]
-    fn simple_1() {
-        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
-        let expected_content = std::fs::read_to_string("util_files/test_data/lexer_output/simple1.plr.txt")
-            .unwrap();
-        for expected_line in expected_content.lines() {
-            let tok = lexer.get_token().unwrap();
-            let line = format!(
-                "{:?} \"{}\", starts at col: {}",
-                tok,
-                lexer.token_str(),
-                lexer.token_start_column()
-            );
-            assert_eq!(line, expected_line, "Wrong token parsing");
-            if tok == Token::EOF {
-                break;
+    #[cfg(feature = "mmap")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(file = %path.display())))]
+    fn _open_mmap(self, path: &Path) -> LexResult<Lexer> {
+        let file = std::fs::File::open(path).map_err(|e| LexicError::io(path, e))?;
+        // Safety: the map is never written to, see `Lexer::from_mmap` for
+        // the file being changed by others
+        let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| LexicError::io(path, e))?;
+        self.validate_utf8(path, &map)?;
+        Ok(Lexer::_with_config(
+            path.to_path_buf(),
+            Source::Mapped(Arc::new(map)),
+            self,
+        ))
+    }
+
+    /// Decompresses a gzip compressed source file and creates a lexer for
+    /// it with this config, see [`Lexer::from_gzip`].
+    #[cfg(feature = "flate2")]
+    #[inline]
+    pub fn open_gzip(self, path: impl AsRef<Path>) -> LexResult<Lexer> {
+        self._open_gzip(path.as_ref())
+    }
+
+    #[cfg(feature = "flate2")]
+    fn _open_gzip(self, path: &Path) -> LexResult<Lexer> {
+        let file = std::fs::File::open(path).map_err(|e| LexicError::io(path, e))?;
+        // Errors point to the source it holds, `a.plr` for `a.plr.gz`
+        let name = match path.extension() {
+            Some(extension) if extension == "gz" => path.with_extension(""),
+            _ => path.to_path_buf(),
+        };
+        let mut decoder = flate2::read::GzDecoder::new(io::BufReader::new(file));
+        self._from_reader(name, &mut decoder)
+    }
+
+    /// Whether trivia (comments) should be returned as tokens. When not
+    /// preserved, which is the default, comments are skipped.
+    pub fn preserve_trivia(mut self, preserve: bool) -> Self {
+        self.preserve_trivia = preserve;
+        self
+    }
+
+    /// Whether parenthesized regions should also be delimited by `GroupBegin`
+    /// and `GroupEnd`, so they look like any other group. The markers come
+    /// inside the parens: `ParensOpen GroupBegin ... GroupEnd ParensClose`.
+    /// A close with nothing open gets no `GroupEnd`, so the markers always
+    /// pair up. Disabled by default.
+    pub fn emit_group_markers(mut self, emit: bool) -> Self {
+        self.emit_group_markers = emit;
+        self
+    }
+
+    /// The pairs of delimiters for groups, lexed as `ParensOpen` and
+    /// `ParensClose`, only `(` and `)` by default. Groups must be closed with
+    /// the delimiter matching the one that opened them, otherwise lexing
+    /// fails with [`LexicError::MismatchedGroupClose`]. Characters that
+    /// already mean something, like `"`, are rejected by
+    /// [`LexerConfig::validate`].
+    pub fn group_delimiters(mut self, pairs: &[(char, char)]) -> Self {
+        self.group_delimiters = pairs.to_vec();
+        self
+    }
+
+    /// Whether [`Lexer::tokenize_all`] also gives the range of each token in
+    /// characters, as `Tokenized::char_ranges`, for tools that don't count
+    /// in bytes. Costs a scan of the source. Disabled by default.
+    pub fn char_ranges(mut self, char_ranges: bool) -> Self {
+        self.char_ranges = char_ranges;
+        self
+    }
+
+    /// Whether [`Lexer::tokenize_all`] reserves room for a token every
+    /// [`LexerConfig::BYTES_PER_TOKEN_HINT`] bytes of source up front, instead
+    /// of growing the vectors as it goes. Saves reallocations on large
+    /// sources, at the cost of some unused memory. Disabled by default.
+    pub fn preallocate_tokens(mut self, preallocate_tokens: bool) -> Self {
+        self.preallocate_tokens = preallocate_tokens;
+        self
+    }
+
+    /// Whether a `(` closed right away by a `)`, with only whitespace between
+    /// them, is lexed as a single `Token::Unit` instead of `ParensOpen` and
+    /// `ParensClose`. Disabled by default.
+    pub fn unit_token(mut self, unit_token: bool) -> Self {
+        self.unit_token = unit_token;
+        self
+    }
+
+    /// Which characters start a line comment, `#` by default.
+    pub fn comment_style(mut self, style: CommentStyle) -> Self {
+        self.comment_style = style;
+        self
+    }
+
+    /// Whether a `:` immediately followed by a newline is lexed as
+    /// `BlockColon` instead of `Colon`. Disabled by default.
+    pub fn block_colons(mut self, block_colons: bool) -> Self {
+        self.block_colons = block_colons;
+        self
+    }
+
+    /// How many columns a tab advances to, [`LexerConfig::DEFAULT_TAB_WIDTH`]
+    /// by default. A tab moves the column to the next multiple of the width,
+    /// as editors do.
+    ///
+    /// # Panics
+    /// If the width is zero.
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        assert!(tab_width > 0, "Tab width must be at least 1");
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// How many errors [`Lexer::tokenize_all`] collects before giving up,
+    /// [`LexerConfig::DEFAULT_MAX_ERRORS`] by default. Must be at least 1.
+    pub fn max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// The longest a string literal can be, in bytes and quotes included,
+    /// longer ones are a [`LexicError::StringTooLong`] as soon as the limit is
+    /// passed, and the rest of the string is skipped. Strings with
+    /// interpolations are only checked up to the first `${`. Unlimited by
+    /// default.
+    pub fn max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = Some(max_string_len);
+        self
+    }
+
+    /// Whether text between backticks is lexed as `Token::InlineCode`, for
+    /// documentation oriented dialects. There are no escapes inside inline
+    /// code, it ends at the next backtick. Disabled by default.
+    pub fn inline_code(mut self, inline_code: bool) -> Self {
+        self.inline_code = inline_code;
+        self
+    }
+
+    /// Whether `#if FLAG` and `#endif` lines are conditional compilation
+    /// directives. The lines between them are only lexed if the flag is
+    /// defined with [`LexerConfig::define`], otherwise they produce no
+    /// tokens. Directive lines have nothing else and may be nested. Since
+    /// they start with `#`, those lines are never comments. Disabled by
+    /// default.
+    pub fn directives(mut self, directives: bool) -> Self {
+        self.directives = directives;
+        self
+    }
+
+    /// Defines a flag for `#if` directives, see [`LexerConfig::directives`].
+    pub fn define(mut self, flag: impl Into<String>) -> Self {
+        self.defined_flags.insert(flag.into());
+        self
+    }
+
+    /// Whether keywords are matched ignoring ASCII case, so `Let` is the `let`
+    /// keyword. Each keyword written in another case is reported as a
+    /// [`LexicWarning::CaseFoldedKeyword`], see [`Lexer::warnings`].
+    /// Disabled by default.
+    pub fn case_insensitive_keywords(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive_keywords = case_insensitive;
+        self
+    }
+
+    /// Whether the [`KEYWORDS`] are lexed as `Keyword`, otherwise they are
+    /// plain identifiers. Enabled by default.
+    pub fn keywords(mut self, keywords: bool) -> Self {
+        self.keywords = keywords;
+        self
+    }
+
+    /// The names lexed as `NamedLiteral` instead of identifiers or keywords,
+    /// as `true`, `false` and `null` in a JSON like dialect. Replaces the
+    /// names set before, none by default.
+    pub fn named_literals(mut self, names: &[&str]) -> Self {
+        self.named_literals = names.iter().map(|&name| name.to_owned()).collect();
+        self
+    }
+
+    /// Whether `+`, `-`, `*` and `/` are lexed as `Plus`, `Minus`, `Star` and
+    /// `Slash`. Comments still win, so with [`CommentStyle::DoubleSlash`] a
+    /// `//` starts a comment instead of being two `Slash`. Disabled by
+    /// default.
+    pub fn arithmetic_operators(mut self, arithmetic_operators: bool) -> Self {
+        self.arithmetic_operators = arithmetic_operators;
+        self
+    }
+
+    /// Whether an identifier right before a string, as in `foo"bar"`, is a
+    /// [`LexicError::IdentifierBeforeString`]. Usually a typo, either a
+    /// missing operator or a string prefix the language doesn't have. The
+    /// error comes right after the identifier, and both it and the string are
+    /// still lexed. Disabled by default.
+    pub fn adjacent_string_errors(mut self, adjacent_string_errors: bool) -> Self {
+        self.adjacent_string_errors = adjacent_string_errors;
+        self
+    }
+
+    /// Whether identifiers are case insensitive, so `Foo` and `foo` are the
+    /// same name. The lexemes keep their case, only
+    /// [`Lexer::normalized_lexeme`] is lowercased. Disabled by default.
+    pub fn case_insensitive_identifiers(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive_identifiers = case_insensitive;
+        self
+    }
+
+    /// Where the source starts, as the line and column of its first
+    /// character, both counting from 1. Meant for snippets taken from a
+    /// larger document, such as a code block in markdown, so that positions
+    /// are reported as in the document. `(1, 1)` by default.
+    ///
+    /// # Panics
+    /// If the line or the column is zero.
+    pub fn initial_position(mut self, line: usize, column: usize) -> Self {
+        assert!(line > 0 && column > 0, "Lines and columns start at 1");
+        self.initial_position = (line - 1, column - 1);
+        self
+    }
+
+    /// The line separating documents for [`LexerConfig::split_documents`],
+    /// `---` by default.
+    pub fn document_separator(mut self, separator: impl Into<String>) -> Self {
+        self.document_separator = separator.into();
+        self
+    }
+
+    /// Splits a source packing many documents, separated by lines with only
+    /// the [`LexerConfig::document_separator`], and creates a lexer with this
+    /// config for each. Each lexer starts at the position of its document in
+    /// the whole source, so errors point to the right line. The separator
+    /// lines are in no document.
+    pub fn split_documents(self, name: impl Into<PathBuf>, source: &str) -> Vec<Lexer> {
+        let name = name.into();
+        let (first_line, first_column) = self.initial_position;
+        let document = |text: &str, line: usize, column: usize| {
+            self.clone()
+                .initial_position(line + 1, column + 1)
+                .build(name.clone(), text)
+        };
+
+        let mut lexers = Vec::new();
+        let (mut start, mut start_line, mut start_column) = (0, first_line, first_column);
+        let mut line_start = 0;
+        for (line, text) in source.split_inclusive('\n').enumerate() {
+            if text.trim_end_matches(['\n', '\r']) == self.document_separator {
+                lexers.push(document(
+                    &source[start..line_start],
+                    start_line,
+                    start_column,
+                ));
+                start = line_start + text.len();
+                start_line = first_line + line + 1;
+                start_column = 0;
+            }
+            line_start += text.len();
+        }
+        lexers.push(document(&source[start..], start_line, start_column));
+        lexers
+    }
+
+    /// Whether `${expression}` inside strings is an interpolation. A string
+    /// with interpolations is lexed as `StringStart`, then for each
+    /// interpolation `InterpolationStart`, the tokens of the expression and
+    /// `InterpolationEnd`, with `StringFragment`s for the text between them,
+    /// and at last `StringEnd`. Strings without interpolations are still
+    /// `StringLiteral`s. A literal `$` can be escaped as `\$`. Disabled by
+    /// default.
+    pub fn string_interpolation(mut self, string_interpolation: bool) -> Self {
+        self.string_interpolation = string_interpolation;
+        self
+    }
+}
+
+/// The tokens and errors of a source, lexed lazily by
+/// [`Lexer::errors_as_iterator`]. Ends at `EOF`, which is not yielded, or
+/// right after the `LexicError::TooManyErrors`.
+pub struct Recovering<'a> {
+    lexer: &'a mut Lexer,
+    errors: usize,
+    done: bool,
+}
+
+impl Iterator for Recovering<'_> {
+    type Item = LexResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let lexer = &mut *self.lexer;
+        match lexer.get_token() {
+            Ok(Token::EOF) => {
+                self.done = true;
+                None
+            }
+            Ok(tok) => Some(Ok(tok)),
+            Err(_) if self.errors >= lexer.config.max_errors => {
+                self.done = true;
+                Some(Err(LexicError::TooManyErrors {
+                    file: lexer.input_filepath.clone(),
+                    line: lexer.current_line + 1,
+                    column: lexer.current_column + 1,
+                    offset: lexer.input_head,
+                    limit: lexer.config.max_errors,
+                }))
+            }
+            Err(e) => {
+                self.errors += 1;
+                lexer.recover();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Every token of a source, as lexed by [`Lexer::tokenize_all`]. The tokens
+/// and their ranges are kept in parallel vectors, `EOF` is not included.
+#[derive(Debug, Default)]
+pub struct Tokenized {
+    pub tokens: Vec<Token>,
+    pub ranges: Vec<Range<usize>>,
+    /// The same ranges in characters instead of bytes, only when enabled
+    /// with [`LexerConfig::char_ranges`]
+    pub char_ranges: Vec<Range<usize>>,
+    pub errors: Vec<LexicError>,
+}
+
+/// The same as [`Lexer::empty`].
+impl Default for Lexer {
+    #[inline]
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Lexer {
+    const IDENT_BREAKERS: &'static [char] = &[
+        ' ', '\t', '\n', '\r', '(', ')', ':', '"', '%', '}', '?', '\\', '=', '`', '.', '|', '&',
+        '#', '$', '^', '~', '\x0C', '\x0B',
+    ];
+    /// Break identifiers only when enabled, see
+    /// [`LexerConfig::arithmetic_operators`].
+    const ARITHMETIC_OPERATORS: &'static [char] = &['+', '-', '*', '/'];
+    /// Form feeds and vertical tabs are whitespace too, they only advance
+    /// the column.
+    const WHITESPACE: &'static [char] = &[' ', '\t', '\n', '\x0C', '\x0B'];
+    /// Characters that may follow a `\` inside a string literal.
+    const ESCAPABLE: &'static [char] = &['\\', '"', 'n', 't', 'r', '0', '$'];
+
+    /// Creates a new lexer for a source file, with the default config.
+    ///
+    /// This only reads the file and hands it to [`Lexer::from_source`]. If
+    /// the contents are already in memory, prefer calling `from_source`
+    /// directly instead of reading the file twice. For other options use
+    /// [`LexerConfig`].
+    #[inline]
+    pub fn new(path: impl AsRef<Path>) -> LexResult<Self> {
+        LexerConfig::default().open(path)
+    }
+
+    /// Creates a new lexer for source code already in memory, with the
+    /// default config. The `name` is used in place of the file path when
+    /// reporting errors.
+    #[inline]
+    pub fn from_source(name: impl Into<PathBuf>, code: impl Into<String>) -> Self {
+        LexerConfig::default().build(name, code)
+    }
+
+    /// Creates a new lexer for a static source, as code compiled into the
+    /// binary, with the default config. The source is borrowed instead of
+    /// copied, and [`Lexer::static_token_str`] gives lexemes that outlive
+    /// the lexer.
+    #[inline]
+    pub fn from_static(name: impl Into<PathBuf>, code: &'static str) -> Self {
+        LexerConfig::default().build_static(name, code)
+    }
+
+    /// Creates a lexer for an empty source named `<empty>`, which only
+    /// returns `EOF`. Handy as a placeholder, as in parser tests.
+    #[inline]
+    pub fn empty() -> Self {
+        Self::from_source("<empty>", "")
+    }
+
+    /// Creates a new lexer for raw bytes, with the default config. Fails if
+    /// the bytes are not valid UTF-8.
+    #[inline]
+    pub fn from_bytes(name: impl Into<PathBuf>, bytes: &[u8]) -> LexResult<Self> {
+        LexerConfig::default().build_from_bytes(name, bytes)
+    }
+
+    /// Creates a lexer for each document of a source, with the default config.
+    /// See [`LexerConfig::split_documents`].
+    #[inline]
+    pub fn split_documents(name: impl Into<PathBuf>, source: &str) -> Vec<Self> {
+        LexerConfig::default().split_documents(name, source)
+    }
+
+    /// Creates a new lexer for a source read from a reader, with the default
+    /// config. See [`LexerConfig::from_reader`].
+    #[inline]
+    pub fn from_reader(name: impl Into<PathBuf>, reader: impl io::Read) -> LexResult<Self> {
+        LexerConfig::default().from_reader(name, reader)
+    }
+
+    /// Creates a new lexer for a memory mapped source file, with the default
+    /// config. The file is validated as UTF-8 once and then lexed from the
+    /// map, without copying it to memory, unless edited by
+    /// [`Lexer::relex_line`]. Fails with `LexicError::InvalidUtf8` if the
+    /// file is not valid UTF-8.
+    ///
+    /// The file must not be changed while the lexer is alive, other
+    /// processes truncating or writing to it is undefined behavior.
+    #[cfg(feature = "mmap")]
+    #[inline]
+    pub fn from_mmap(path: impl AsRef<Path>) -> LexResult<Self> {
+        LexerConfig::default().open_mmap(path)
+    }
+
+    /// Creates a new lexer for a gzip compressed source file, with the
+    /// default config. The file is decompressed as it is read, and named
+    /// without the `.gz` extension when reporting errors.
+    #[cfg(feature = "flate2")]
+    #[inline]
+    pub fn from_gzip(path: impl AsRef<Path>) -> LexResult<Self> {
+        LexerConfig::default().open_gzip(path)
+    }
+
+    /// Creates a new lexer for a snippet of a larger document, with the
+    /// default config. The `line` and `column`, counting from 1, are where
+    /// the snippet starts in the document, so errors point to the document
+    /// instead of the snippet. See [`LexerConfig::initial_position`].
+    #[inline]
+    pub fn with_initial_position(
+        name: impl Into<PathBuf>,
+        code: impl Into<String>,
+        line: usize,
+        column: usize,
+    ) -> Self {
+        LexerConfig::default()
+            .initial_position(line, column)
+            .build(name, code)
+    }
+
+    fn _with_config(input_filepath: PathBuf, code: Source, config: LexerConfig) -> Self {
+        if let Err(e) = config.validate() {
+            panic!("Invalid lexer config: {}", e);
+        }
+        let (current_line, current_column) = config.initial_position;
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("lexer", file = %input_filepath.display());
+        #[cfg(feature = "tracing")]
+        tracing::debug!(parent: &span, bytes = code.len(), "Created lexer");
+        Self {
+            code,
+            input_filepath,
+            current_line,
+            current_column,
+            input_head: 0,
+            state: State::Looking,
+            token_range: 0..0,
+            current_token: None,
+            last_token: None,
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            config,
+            interpolations: Vec::new(),
+            conditions: Vec::new(),
+            open_groups: Vec::new(),
+            token_column: current_column,
+            at_line_start: true,
+            token_starts_line: true,
+            line_offsets: OnceCell::new(),
+            lookahead: VecDeque::new(),
+            pending_error: None,
+            #[cfg(feature = "tracing")]
+            span,
+            #[cfg(feature = "tracing")]
+            token_count: 0,
+        }
+    }
+
+    /// Creates a lexer for the same source, with the same config, that starts
+    /// at the given byte offset. The source is shared rather than copied, so
+    /// this is cheap enough to lex a source in chunks. The positions of its
+    /// tokens and errors are the same as this lexer would give them.
+    pub fn clone_at(&self, offset: usize) -> LexResult<Lexer> {
+        if !self.code.is_char_boundary(offset) {
+            let (line, column) = self.line_col_at(offset);
+            return Err(LexicError::InvalidOffset {
+                file: self.input_filepath.clone(),
+                line,
+                column,
+                offset,
+            });
+        }
+        let mut lexer = Lexer::_with_config(
+            self.input_filepath.clone(),
+            self.code.clone(),
+            self.config.clone(),
+        );
+        let (line, column) = self.line_col_at(offset);
+        let line_start = self.code[..offset].rfind('\n').map_or(0, |i| i + 1);
+        lexer.input_head = offset;
+        lexer.token_range = offset..offset;
+        lexer.current_line = line - 1;
+        lexer.current_column = column - 1;
+        lexer.token_column = column - 1;
+        lexer.at_line_start = self.code[line_start..offset]
+            .chars()
+            .all(|c| Self::WHITESPACE.contains(&c));
+        Ok(lexer)
+    }
+
+    /// The byte offset where each line starts, computing them if the source
+    /// changed since the last time.
+    fn line_offsets(&self) -> &[usize] {
+        self.line_offsets
+            .get_or_init(|| Self::compute_line_offsets(&self.code))
+    }
+
+    fn compute_line_offsets(code: &str) -> Vec<usize> {
+        std::iter::once(0)
+            .chain(code.match_indices('\n').map(|(i, _)| i + 1))
+            .collect()
+    }
+
+    #[inline]
+    pub fn config(&self) -> &LexerConfig {
+        &self.config
+    }
+
+    /// Lexes the whole source, recovering from errors instead of stopping at
+    /// the first one. After the maximum number of errors is reached (see
+    /// [`LexerConfig::max_errors`]) lexing stops and a final
+    /// `LexicError::TooManyErrors` is added.
+    pub fn tokenize_all(&mut self) -> Tokenized {
+        let hint = if self.config.preallocate_tokens {
+            self.code.len() / LexerConfig::BYTES_PER_TOKEN_HINT
+        } else {
+            0
+        };
+        self.tokenize_all_with_capacity(hint)
+    }
+
+    /// The same as [`Lexer::tokenize_all`], reserving room for `hint` tokens
+    /// before lexing. The result is the same whatever the hint.
+    pub fn tokenize_all_with_capacity(&mut self, hint: usize) -> Tokenized {
+        let mut tokenized = Tokenized {
+            tokens: Vec::with_capacity(hint),
+            ranges: Vec::with_capacity(hint),
+            ..Tokenized::default()
+        };
+        loop {
+            match self.get_token() {
+                Ok(Token::EOF) => break,
+                Ok(tok) => {
+                    tokenized.tokens.push(tok);
+                    tokenized.ranges.push(self.token_range.clone());
+                }
+                Err(_) if tokenized.errors.len() >= self.config.max_errors => {
+                    tokenized.errors.push(LexicError::TooManyErrors {
+                        file: self.input_filepath.clone(),
+                        line: self.current_line + 1,
+                        column: self.current_column + 1,
+                        offset: self.input_head,
+                        limit: self.config.max_errors,
+                    });
+                    break;
+                }
+                Err(e) => {
+                    tokenized.errors.push(e);
+                    self.recover();
+                }
+            }
+        }
+        if self.config.char_ranges {
+            tokenized.char_ranges = self.char_ranges_of(&tokenized.ranges);
+        }
+        tokenized
+    }
+
+    /// Converts byte ranges in order to character ranges, counting the
+    /// characters between each range and the one before.
+    fn char_ranges_of(&self, ranges: &[Range<usize>]) -> Vec<Range<usize>> {
+        let (mut byte, mut chars) = (0, 0);
+        ranges
+            .iter()
+            .map(|range| {
+                if range.start < byte {
+                    (byte, chars) = (0, 0);
+                }
+                let start = chars + self.code[byte..range.start].chars().count();
+                let end = start + self.code[range.clone()].chars().count();
+                (byte, chars) = (range.end, end);
+                start..end
+            })
+            .collect()
+    }
+
+    /// Lexes the rest of the source into its tokens and their text, `EOF`
+    /// not included. The text is copied, so the tokens can be kept after the
+    /// lexer is gone. Stops at the first error, see
+    /// [`Lexer::tokenize_all`] to recover from errors instead.
+    pub fn as_tokens_with_text(&mut self) -> LexResult<Vec<(Token, String)>> {
+        let mut tokens = Vec::new();
+        loop {
+            match self.get_token()? {
+                Token::EOF => break Ok(tokens),
+                tok => tokens.push((tok, self.token_str().to_owned())),
+            }
+        }
+    }
+
+    /// Takes a snapshot of where the lexer is, to backtrack to it with
+    /// [`Lexer::restore`]. Only the position and the state are copied, not
+    /// the source, so this is much cheaper than cloning the lexer.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            input_head: self.input_head,
+            current_line: self.current_line,
+            current_column: self.current_column,
+            state: self.state.clone(),
+            token_range: self.token_range.clone(),
+            current_token: self.current_token,
+            last_token: self.last_token,
+            warning_count: self.warnings.len(),
+            open_groups: self.open_groups.clone(),
+            conditions: self.conditions.clone(),
+            interpolations: self.interpolations.clone(),
+            token_column: self.token_column,
+            at_line_start: self.at_line_start,
+            token_starts_line: self.token_starts_line,
+            lookahead: self.lookahead.clone(),
+            pending_error: self.pending_error.clone(),
+        }
+    }
+
+    /// Goes back to a checkpoint of this lexer, so the tokens after it are
+    /// lexed again. Warnings found after the checkpoint are dropped, as they
+    /// will be found again, but errors kept for [`Lexer::take_errors`] are
+    /// not, [`sort_errors`] removes the repeated ones. The checkpoint must be
+    /// taken from this lexer and the source must not have been edited since,
+    /// see [`Lexer::relex_line`].
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.input_head = checkpoint.input_head;
+        self.current_line = checkpoint.current_line;
+        self.current_column = checkpoint.current_column;
+        self.state = checkpoint.state;
+        self.token_range = checkpoint.token_range;
+        self.current_token = checkpoint.current_token;
+        self.last_token = checkpoint.last_token;
+        self.warnings.truncate(checkpoint.warning_count);
+        self.open_groups = checkpoint.open_groups;
+        self.conditions = checkpoint.conditions;
+        self.interpolations = checkpoint.interpolations;
+        self.token_column = checkpoint.token_column;
+        self.at_line_start = checkpoint.at_line_start;
+        self.token_starts_line = checkpoint.token_starts_line;
+        self.lookahead = checkpoint.lookahead;
+        self.pending_error = checkpoint.pending_error;
+    }
+
+    /// Lexes up to a byte offset and returns the token under it, with its
+    /// range, as the word to complete in an editor. With no token under it,
+    /// the token ending right at the offset counts too, as it may still be
+    /// being typed. Returns `None` if the offset is inside whitespace or past
+    /// the end.
+    ///
+    /// Errors on the way are skipped. Lexing starts from where the lexer is,
+    /// so the offsets must be queried in order, use [`Lexer::clone_at`] to
+    /// start over.
+    pub fn token_at(&mut self, offset: usize) -> Option<(Token, Range<usize>)> {
+        // The current token may already be the one
+        let mut current = self.current_token.filter(|&tok| tok != Token::EOF);
+        // The token ending at the offset, unless the next one starts there
+        let mut ending = None;
+        loop {
+            if let Some(tok) = current {
+                let range = self.token_range.clone();
+                if range.start > offset {
+                    return ending;
+                }
+                if range.contains(&offset) {
+                    return Some((tok, range));
+                }
+                if range.end == offset {
+                    ending = Some((tok, range));
+                }
+            }
+            current = match self.get_token() {
+                Ok(Token::EOF) => return ending,
+                Ok(tok) => Some(tok),
+                Err(_) => {
+                    self.recover();
+                    None
+                }
+            };
+        }
+    }
+
+    /// Lexes the rest of the source lazily, recovering from errors the same
+    /// way as [`Lexer::tokenize_all`]. Errors are yielded between the tokens,
+    /// in the order they are found.
+    pub fn errors_as_iterator(&mut self) -> Recovering<'_> {
+        Recovering {
+            lexer: self,
+            errors: 0,
+            done: false,
+        }
+    }
+
+    /// Gets the next token, recovering from errors the same way as
+    /// [`Lexer::tokenize_all`]. The errors are kept in the lexer until taken
+    /// with [`Lexer::take_errors`], so this never fails, lexing goes on
+    /// until `EOF`. There is no [`LexerConfig::max_errors`] limit, the
+    /// errors are meant to be taken as they are found.
+    pub fn get_token_recovering(&mut self) -> Token {
+        loop {
+            match self.get_token() {
+                Ok(tok) => break tok,
+                Err(e) => {
+                    self.errors.push(e);
+                    self.recover();
+                }
+            }
+        }
+    }
+
+    /// Takes the errors found by [`Lexer::get_token_recovering`] since the
+    /// last time, in the order they were found. Lexing is not affected, the
+    /// next errors are kept for the next call.
+    pub fn take_errors(&mut self) -> Vec<LexicError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// The errors found by [`Lexer::get_token_recovering`] and not taken yet
+    /// with [`Lexer::take_errors`], in the order they were found.
+    #[inline]
+    pub fn diagnostics(&self) -> &[LexicError] {
+        &self.errors
+    }
+
+    /// Skips the character that caused an error, so lexing can go on.
+    fn recover(&mut self) {
+        match self.state.clone() {
+            // Unknown escape sequence, the rest of the string is still fine
+            State::AccString {
+                range,
+                start_line,
+                start_column,
+                continued,
+                ..
+            } if self.getc().is_some() => {
+                self.advance();
+                self.state = State::AccString {
+                    range: range.start..self.input_head,
+                    escaping: false,
+                    start_line,
+                    start_column,
+                    continued,
+                };
+            }
+            State::PastError => self.state = State::Looking,
+            State::SkippingString { escaping } => self.skip_string(escaping),
+            _ => {
+                self.advance();
+                self.state = State::Looking;
+            }
+        }
+    }
+
+    pub fn get_token(&mut self) -> LexResult<Token> {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.clone().entered();
+        let result = match self.lookahead.pop_front() {
+            Some(lookahead) => {
+                self.token_range = lookahead.range;
+                self.token_column = lookahead.column;
+                self.token_starts_line = lookahead.starts_line;
+                lookahead.result
+            }
+            None => self.lex_token(),
+        };
+        self.current_token = result.as_ref().ok().copied();
+        if let Ok(token) = result {
+            if token != Token::EOF {
+                self.last_token = Some(token);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(Token::EOF) => tracing::debug!(tokens = self.token_count, "Reached the end"),
+            Ok(_) => self.token_count += 1,
+            Err(e) => tracing::debug!(error = %e, "Lexic error"),
+        }
+        result
+    }
+
+    /// Consumes the next token, failing with `LexicError::UnexpectedToken` if
+    /// it is not the expected one.
+    pub fn expect(&mut self, expected: Token) -> LexResult<()> {
+        let found = self.get_token()?;
+        if found == expected {
+            Ok(())
+        } else {
+            Err(LexicError::UnexpectedToken {
+                expected,
+                found,
+                file: self.input_filepath.clone(),
+                line: self.token_line() + 1,
+                column: self.token_column + 1,
+                offset: self.token_range.start,
+            })
+        }
+    }
+
+    /// Checks that the whole input was consumed, that is, the next token is
+    /// `Token::EOF`.
+    #[inline]
+    pub fn expect_eof(&mut self) -> LexResult<()> {
+        self.expect(Token::EOF)
+    }
+
+    /// The next token, without consuming it. Same as `peek_nth(0)`.
+    #[inline]
+    pub fn peek_token(&mut self) -> LexResult<Token> {
+        self.peek_nth(0)
+    }
+
+    /// The token `n` tokens after the next one, without consuming any. The
+    /// tokens are lexed on demand and kept until [`Lexer::get_token`] returns
+    /// them, the current token stays the same.
+    ///
+    /// Nothing is lexed past an error or the end of the input, so peeking
+    /// beyond them gives that same error or `Token::EOF`. Note that
+    /// [`Lexer::remaining`] does not include peeked tokens.
+    pub fn peek_nth(&mut self, n: usize) -> LexResult<Token> {
+        while self.lookahead.len() <= n
+            && !matches!(
+                self.lookahead.back(),
+                Some(Lookahead {
+                    result: Err(_) | Ok(Token::EOF),
+                    ..
+                })
+            )
+        {
+            let current_range = self.token_range.clone();
+            let current_column = self.token_column;
+            let current_starts_line = self.token_starts_line;
+            let result = self.lex_token();
+            self.lookahead.push_back(Lookahead {
+                result,
+                range: std::mem::replace(&mut self.token_range, current_range),
+                column: std::mem::replace(&mut self.token_column, current_column),
+                starts_line: std::mem::replace(&mut self.token_starts_line, current_starts_line),
+            });
+        }
+        // Safe to unwrap, the loop leaves at least one token
+        let lookahead = self.lookahead.get(n).or(self.lookahead.back()).unwrap();
+        lookahead.result.clone()
+    }
+
+    /// Puts the current token back, so the next [`Lexer::get_token`] returns
+    /// it again. There is no current token until then. Only one token can be
+    /// put back, this does nothing without a current token, as after an
+    /// error or right after rewinding.
+    pub fn rewind_token(&mut self) {
+        if let Some(tok) = self.current_token.take() {
+            self.lookahead.push_front(Lookahead {
+                result: Ok(tok),
+                range: self.token_range.clone(),
+                column: self.token_column,
+                starts_line: self.token_starts_line,
+            });
+        }
+    }
+
+    fn lex_token(&mut self) -> LexResult<Token> {
+        self.token_range = 0..0;
+        match self.state {
+            State::PastError => self.state = State::Looking,
+            State::SkippingString { escaping } => self.skip_string(escaping),
+            _ => (),
+        }
+        if let Some(error) = self.pending_error.take() {
+            // Nothing to skip when recovering, the next token is right after
+            self.state = State::PastError;
+            self.token_range = error.offset()..error.offset();
+            return Err(error);
+        }
+        loop {
+            let current_c = self.getc();
+            // Every token starts being lexed from one of these states
+            let starts_token = match &self.state {
+                State::Looking | State::OpeningGroup | State::InterpolationStart => true,
+                State::AccString { range, .. } => range.is_empty(),
+                _ => false,
+            };
+            if starts_token {
+                self.token_column = self.current_column;
+                self.token_starts_line = self.at_line_start;
+            }
+
+            // Null bytes are only fine inside string literals, anywhere else it
+            // is most likely a binary file being lexed by mistake
+            if current_c == Some('\0') && !matches!(self.state, State::AccString { .. }) {
+                break Err(LexicError::NullByte {
+                    file: self.input_filepath.clone(),
+                    line: self.current_line + 1,
+                    column: self.current_column + 1,
+                    offset: self.input_head,
+                });
+            }
+
+            match self.state.clone() {
+                State::Looking => match current_c {
+                    Some(c) if c.is_alphabetic() || c == '_' => {
+                        self.state = State::AccIdent {
+                            range: self.input_head..(self.input_head + c.len_utf8()),
+                        };
+                        self.advance();
+                    }
+                    Some(c) if c.is_ascii_digit() => {
+                        self.state = State::AccNumber {
+                            range: self.input_head..(self.input_head + c.len_utf8()),
+                            is_float: false,
+                        };
+                        self.advance();
+                    }
+                    Some('%') => {
+                        self.token_range = self.input_head..(self.input_head + '%'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Percent);
+                    }
+                    Some('=') => {
+                        self.token_range = self.input_head..(self.input_head + '='.len_utf8());
+                        self.advance();
+                        break Ok(Token::Equals);
+                    }
+                    Some('?') => {
+                        self.token_range = self.input_head..(self.input_head + '?'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Question);
+                    }
+                    // Single characters for now, `||` and `&&` would be
+                    // checked first here
+                    Some('|') => {
+                        self.token_range = self.input_head..(self.input_head + '|'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Pipe);
+                    }
+                    Some('&') => {
+                        self.token_range = self.input_head..(self.input_head + '&'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Ampersand);
+                    }
+                    Some('$') => {
+                        self.token_range = self.input_head..(self.input_head + '$'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Dollar);
+                    }
+                    // Single characters for now, compound forms as `^=` would
+                    // be checked first here
+                    Some('^') => {
+                        self.token_range = self.input_head..(self.input_head + '^'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Caret);
+                    }
+                    Some('~') => {
+                        self.token_range = self.input_head..(self.input_head + '~'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Tilde);
+                    }
+                    // The longest run of up to three dots wins, so `...` is
+                    // never lexed as `..` and `.`
+                    Some('.') => {
+                        let dots = (1..3)
+                            .take_while(|&n| self.peek_char(n) == Some('.'))
+                            .count()
+                            + 1;
+                        self.token_range = self.input_head..(self.input_head + dots);
+                        for _ in 0..dots {
+                            self.advance();
+                        }
+                        break Ok(match dots {
+                            1 => Token::Dot,
+                            2 => Token::DotDot,
+                            _ => Token::Ellipsis,
+                        });
+                    }
+                    Some('(') if self.config.unit_token && self.unit_length().is_some() => {
+                        // Safe to unwrap, just checked it is a unit
+                        let end = self.input_head + self.unit_length().unwrap();
+                        self.token_range = self.input_head..end;
+                        while self.input_head < end {
+                            self.advance();
+                        }
+                        break Ok(Token::Unit);
+                    }
+                    Some(c) if self.config.opens_group(c).is_some() => {
+                        // Safe to unwrap, just checked it opens a group
+                        self.open_groups.push(self.config.opens_group(c).unwrap());
+                        self.token_range = self.input_head..(self.input_head + c.len_utf8());
+                        self.state = if self.config.emit_group_markers {
+                            State::OpeningGroup
+                        } else {
+                            State::Looking
+                        };
+                        self.advance();
+                        break Ok(Token::ParensOpen);
+                    }
+                    Some(c) if self.config.closes_group(c) => {
+                        // Closing with nothing open is left for the parser
+                        // to report
+                        if let Some(&expected) = self.open_groups.last().filter(|&&e| e != c) {
+                            break Err(LexicError::MismatchedGroupClose {
+                                found: c,
+                                expected,
+                                file: self.input_filepath.clone(),
+                                line: self.current_line + 1,
+                                column: self.current_column + 1,
+                                offset: self.input_head,
+                            });
+                        }
+                        // A stray close has no GroupBegin to match
+                        let was_open = self.open_groups.pop().is_some();
+                        if self.config.emit_group_markers && was_open {
+                            self.token_range = self.input_head..self.input_head;
+                            self.state = State::ClosingGroup;
+                            break Ok(Token::GroupEnd);
+                        }
+                        self.token_range = self.input_head..(self.input_head + c.len_utf8());
+                        self.state = State::Looking;
+                        self.advance();
+                        break Ok(Token::ParensClose);
+                    }
+                    // Assignment in other languages
+                    Some(':') if self.peek_char(1) == Some('=') => {
+                        break Err(LexicError::ReservedPunctuation {
+                            found: ":=",
+                            suggestion: "=",
+                            file: self.input_filepath.clone(),
+                            line: self.current_line + 1,
+                            column: self.current_column + 1,
+                            offset: self.input_head,
+                        });
+                    }
+                    Some(':') if self.peek_char(1) == Some(':') => {
+                        self.token_range = self.input_head..(self.input_head + "::".len());
+                        self.state = State::Looking;
+                        self.advance();
+                        self.advance();
+                        break Ok(Token::ColonColon);
+                    }
+                    Some(':') => {
+                        self.token_range =
+                            self.input_head..(self.input_head + ':'.len_utf8());
+                        self.state = State::Looking;
+                        self.advance();
+                        if self.config.block_colons && self.line_break_at(0) {
+                            break Ok(Token::BlockColon);
+                        }
+                        break Ok(Token::Colon);
+                    }
+                    Some('"') => {
+                        self.state = State::AccString {
+                            range: self.input_head..(self.input_head + '"'.len_utf8()),
+                            escaping: false,
+                            start_line: self.current_line,
+                            start_column: self.current_column,
+                            continued: false,
+                        };
+                        self.advance();
+                    }
+                    Some('`') if self.config.inline_code => {
+                        self.state = State::AccInlineCode {
+                            range: self.input_head..(self.input_head + '`'.len_utf8()),
+                            start_line: self.current_line,
+                            start_column: self.current_column,
+                        };
+                        self.advance();
+                    }
+                    Some('}') if !self.interpolations.is_empty() => {
+                        self.token_range = self.input_head..(self.input_head + '}'.len_utf8());
+                        self.advance();
+                        // Safe to unwrap, just checked it is not empty
+                        let &(start_line, start_column, _) = self.interpolations.last().unwrap();
+                        self.state = State::AccString {
+                            range: self.input_head..self.input_head,
+                            escaping: false,
+                            start_line,
+                            start_column,
+                            continued: true,
+                        };
+                        break Ok(Token::InterpolationEnd);
+                    }
+                    Some('#') if self.at_directive() => {
+                        if let Err(e) = self.directive().and_then(|_| self.skip_excluded()) {
+                            break Err(e);
+                        }
+                    }
+                    Some(_) if self.at_comment_start() => {
+                        let introducer = self.config.comment_style.introducer();
+                        self.state = State::AccComment {
+                            range: self.input_head..(self.input_head + introducer.len()),
+                        };
+                        for _ in introducer.chars() {
+                            self.advance();
+                        }
+                    }
+                    Some(c)
+                        if self.config.arithmetic_operators
+                            && Self::ARITHMETIC_OPERATORS.contains(&c) =>
+                    {
+                        self.token_range = self.input_head..(self.input_head + c.len_utf8());
+                        self.advance();
+                        break Ok(match c {
+                            '+' => Token::Plus,
+                            '-' => Token::Minus,
+                            '*' => Token::Star,
+                            _ => Token::Slash,
+                        });
+                    }
+                    // Comments start with something else, `#` is free
+                    Some('#') => {
+                        self.token_range = self.input_head..(self.input_head + '#'.len_utf8());
+                        self.advance();
+                        break Ok(Token::Hash);
+                    }
+                    Some(c) if Self::WHITESPACE.contains(&c) => {
+                        self.advance();
+                    }
+                    // The start of a `\r\n`, the `\n` ends the line
+                    Some('\r') if self.line_break_at(0) => {
+                        self.advance();
+                    }
+                    // A line continuation, the next line is part of this one
+                    Some('\\') if self.line_break_at(1) => {
+                        let start = self.input_head;
+                        while self.getc() != Some('\n') {
+                            self.advance();
+                        }
+                        self.advance();
+                        self.at_line_start = false;
+                        if self.config.preserve_trivia {
+                            self.token_range = start..self.input_head;
+                            break Ok(Token::LineContinuation);
+                        }
+                    }
+                    Some(c) => break Err(self.err_unexpected_char(c)),
+                    None => match self.interpolations.pop() {
+                        Some((start_line, start_column, start_offset)) => {
+                            break Err(LexicError::UnterminatedString {
+                                file: self.input_filepath.clone(),
+                                line: start_line + 1,
+                                column: start_column + 1,
+                                offset: start_offset,
+                            })
+                        }
+                        None => match self.conditions.pop() {
+                            Some((_, line, column, offset)) => {
+                                break Err(LexicError::UnterminatedIf {
+                                    file: self.input_filepath.clone(),
+                                    line: line + 1,
+                                    column: column + 1,
+                                    offset,
+                                })
+                            }
+                            None => {
+                                self.token_range = self.input_head..self.input_head;
+                                break Ok(Token::EOF);
+                            }
+                        },
+                    },
+                },
+                State::AccIdent { range } => match current_c {
+                    Some(c) if Self::continues_ident(c) => {
+                        self.advance();
+                        self.state = State::AccIdent {
+                            range: range.start..self.input_head,
+                        };
+                    }
+                    // Either an ident breaker or None (as None would unwrap or true)
+                    _ if current_c.map(|c| self.breaks_ident(c)).unwrap_or(true)
+                        || self.at_comment_start() =>
+                    {
+                        self.token_range = range;
+                        self.state = State::Looking;
+                        if self.config.adjacent_string_errors && current_c == Some('"') {
+                            // The identifier is still returned, then the error,
+                            // then the string
+                            self.pending_error = Some(LexicError::IdentifierBeforeString {
+                                identifier: self.token_str().to_owned(),
+                                file: self.input_filepath.clone(),
+                                line: self.current_line + 1,
+                                column: self.current_column + 1,
+                                offset: self.input_head,
+                            });
+                        }
+                        if self.token_str() == "_" {
+                            break Ok(Token::Underscore);
+                        }
+                        if self.config.named_literals.contains(self.token_str()) {
+                            break Ok(Token::NamedLiteral);
+                        }
+                        if !self.config.keywords {
+                            break Ok(Token::Identifier);
+                        }
+                        if is_keyword(self.token_str()) {
+                            break Ok(Token::Keyword);
+                        }
+                        if self.config.case_insensitive_keywords && self.is_case_folded_keyword() {
+                            self.warnings.push(LexicWarning::CaseFoldedKeyword {
+                                lexeme: self.token_str().to_owned(),
+                                file: self.input_filepath.clone(),
+                                line: self.token_line() + 1,
+                                column: self.token_column + 1,
+                                offset: self.token_range.start,
+                            });
+                            break Ok(Token::Keyword);
+                        }
+                        break Ok(Token::Identifier);
+                    }
+                    // I'm sure None would be matched by the above arm, but
+                    // rustc can't tell so we'll unwrap it here, it is Some
+                    // unexpected character for sure. The '\0' is here for
+                    // funsies.
+                    _ => break Err(self.err_unexpected_char(current_c.unwrap_or('\0'))),
+                },
+                // Comments go until the end of the line, the newline itself is
+                // not part of the comment.
+                State::AccComment { range } => match current_c {
+                    Some(_) if !self.line_break_at(0) => {
+                        self.advance();
+                        self.state = State::AccComment {
+                            range: range.start..self.input_head,
+                        };
+                    }
+                    _ => {
+                        self.state = State::Looking;
+                        if self.config.preserve_trivia {
+                            let is_doc = self.code[range.clone()]
+                                .starts_with(self.config.comment_style.doc_marker());
+                            self.token_range = range;
+                            break Ok(if is_doc {
+                                Token::DocComment
+                            } else {
+                                Token::Comment
+                            });
+                        }
+                    }
+                },
+                // A number is digits, optionally followed by a dot and more
+                // digits. Right after the number a `%` makes it a percent
+                // literal, unless an operand follows (as in `10%x`), then it
+                // is the `%` operator. Letters right after the number start
+                // its type suffix.
+                State::AccNumber { range, is_float } => match current_c {
+                    Some(c) if c.is_ascii_digit() => {
+                        self.advance();
+                        self.state = State::AccNumber {
+                            range: range.start..self.input_head,
+                            is_float,
+                        };
+                    }
+                    Some('.')
+                        if !is_float && self.peek_char(1).is_some_and(|c| c.is_ascii_digit()) =>
+                    {
+                        self.advance();
+                        self.state = State::AccNumber {
+                            range: range.start..self.input_head,
+                            is_float: true,
+                        };
+                    }
+                    Some('%') if !self.peek_char(1).is_some_and(Self::starts_operand) => {
+                        self.advance();
+                        self.token_range = range.start..self.input_head;
+                        self.state = State::Looking;
+                        break Ok(Token::PercentLiteral);
+                    }
+                    Some(c) if c.is_alphabetic() || c == '_' => {
+                        self.advance();
+                        self.state = State::AccNumberSuffix {
+                            range: range.start..self.input_head,
+                            is_float,
+                        };
+                    }
+                    _ if current_c.map(|c| self.breaks_ident(c)).unwrap_or(true)
+                        || self.at_comment_start() =>
+                    {
+                        self.token_range = range;
+                        self.state = State::Looking;
+                        break Ok(if is_float {
+                            Token::FloatLiteral
+                        } else {
+                            Token::IntegerLiteral
+                        });
+                    }
+                    _ => break Err(self.err_unexpected_char(current_c.unwrap_or('\0'))),
+                },
+                // Any suffix is fine here, the parser checks it is a type
+                State::AccNumberSuffix { range, is_float } => match current_c {
+                    Some(c) if Self::continues_ident(c) => {
+                        self.advance();
+                        self.state = State::AccNumberSuffix {
+                            range: range.start..self.input_head,
+                            is_float,
+                        };
+                    }
+                    _ if current_c.map(|c| self.breaks_ident(c)).unwrap_or(true)
+                        || self.at_comment_start() =>
+                    {
+                        self.token_range = range;
+                        self.state = State::Looking;
+                        break Ok(if is_float {
+                            Token::FloatLiteral
+                        } else {
+                            Token::IntegerLiteral
+                        });
+                    }
+                    _ => break Err(self.err_unexpected_char(current_c.unwrap_or('\0'))),
+                },
+                State::AccInlineCode {
+                    range,
+                    start_line,
+                    start_column,
+                } => match current_c {
+                    Some('`') => {
+                        self.advance();
+                        self.token_range = range.start..self.input_head;
+                        self.state = State::Looking;
+                        break Ok(Token::InlineCode);
+                    }
+                    Some(_) => {
+                        self.advance();
+                        self.state = State::AccInlineCode {
+                            range: range.start..self.input_head,
+                            start_line,
+                            start_column,
+                        };
+                    }
+                    None => {
+                        break Err(LexicError::UnterminatedInlineCode {
+                            file: self.input_filepath.clone(),
+                            line: start_line + 1,
+                            column: start_column + 1,
+                            offset: range.start,
+                        })
+                    }
+                },
+                // Only reachable with the `${` as the current characters
+                State::InterpolationStart => {
+                    self.token_range = self.input_head..(self.input_head + "${".len());
+                    self.advance();
+                    self.advance();
+                    self.state = State::Looking;
+                    break Ok(Token::InterpolationStart);
+                }
+                State::OpeningGroup => {
+                    self.token_range = self.input_head..self.input_head;
+                    self.state = State::Looking;
+                    break Ok(Token::GroupBegin);
+                }
+                // Turned into `Looking` before lexing anything
+                State::PastError | State::SkippingString { .. } => unreachable!(),
+                // Only reachable with the closing delimiter as the current
+                // character
+                State::ClosingGroup => {
+                    let length = current_c.map_or(0, char::len_utf8);
+                    self.token_range = self.input_head..(self.input_head + length);
+                    self.state = State::Looking;
+                    self.advance();
+                    break Ok(Token::ParensClose);
+                }
+                State::AccString {
+                    range,
+                    escaping,
+                    start_line,
+                    start_column,
+                    continued,
+                } => match current_c {
+                    // Checked as the string goes, not to scan all of a huge one
+                    Some(c)
+                        if !continued
+                            && self.config.max_string_len.is_some_and(|max| {
+                                self.input_head + c.len_utf8() - range.start > max
+                            }) =>
+                    {
+                        self.state = State::SkippingString { escaping };
+                        break Err(LexicError::StringTooLong {
+                            file: self.input_filepath.clone(),
+                            line: start_line + 1,
+                            column: start_column + 1,
+                            offset: range.start,
+                            // Safe to unwrap, just checked on the guard
+                            max: self.config.max_string_len.unwrap(),
+                        });
+                    }
+                    Some(c) if escaping && !Self::ESCAPABLE.contains(&c) => {
+                        break Err(self.err_unknown_escape(c));
+                    }
+                    Some('"') if !escaping => {
+                        self.advance();
+                        self.token_range = range.start..self.input_head;
+                        self.state = State::Looking;
+                        if continued {
+                            self.interpolations.pop();
+                            break Ok(Token::StringEnd);
+                        }
+                        break Ok(Token::StringLiteral);
+                    }
+                    Some('$')
+                        if !escaping
+                            && self.config.string_interpolation
+                            && self.peek_char(1) == Some('{') =>
+                    {
+                        self.state = State::InterpolationStart;
+                        if !continued {
+                            self.interpolations
+                                .push((start_line, start_column, range.start));
+                        }
+                        // Nothing between a `}` and a `${`, no fragment then
+                        if !(continued && range.is_empty()) {
+                            self.token_range = range;
+                            break Ok(if continued {
+                                Token::StringFragment
+                            } else {
+                                Token::StringStart
+                            });
+                        }
+                    }
+                    Some(c) => {
+                        self.advance();
+                        self.state = State::AccString {
+                            range: range.start..self.input_head,
+                            escaping: !escaping && c == '\\',
+                            start_line,
+                            start_column,
+                            continued,
+                        };
+                    }
+                    None => {
+                        let offset = match self.interpolations.last() {
+                            Some(&(_, _, start_offset)) if continued => start_offset,
+                            _ => range.start,
+                        };
+                        break Err(LexicError::UnterminatedString {
+                            file: self.input_filepath.clone(),
+                            line: start_line + 1,
+                            column: start_column + 1,
+                            offset,
+                        });
+                    }
+                },
+            }
+        }
+    }
+
+    /// Skips the rest of a string, up to and including its closing quote,
+    /// without keeping any of it.
+    fn skip_string(&mut self, mut escaping: bool) {
+        while let Some(c) = self.getc() {
+            self.advance();
+            match c {
+                '"' if !escaping => break,
+                _ => escaping = !escaping && c == '\\',
+            }
+        }
+        self.state = State::Looking;
+    }
+
+    #[inline]
+    pub fn getc(&self) -> Option<char> {
+        self.code[self.input_head..].chars().next()
+    }
+
+    /// The character `n` characters ahead of the current one.
+    #[inline]
+    fn peek_char(&self, n: usize) -> Option<char> {
+        self.code[self.input_head..].chars().nth(n)
+    }
+
+    /// Whether a line break, either `\n` or `\r\n`, is `n` characters ahead
+    /// of the current one. Files may mix both, each counts as one line.
+    fn line_break_at(&self, n: usize) -> bool {
+        let mut chars = self.code[self.input_head..].chars().skip(n);
+        match chars.next() {
+            Some('\n') => true,
+            Some('\r') => chars.next() == Some('\n'),
+            _ => false,
+        }
+    }
+
+    /// The length in bytes of the `()` starting at the current `(`, if the
+    /// parens are empty and they delimit a group.
+    fn unit_length(&self) -> Option<usize> {
+        if self.config.opens_group('(') != Some(')') {
+            return None;
+        }
+        let inside = self.remaining().strip_prefix('(')?;
+        let rest = inside.trim_start_matches(Self::WHITESPACE);
+        rest.starts_with(')')
+            .then(|| self.remaining().len() - rest.len() + ')'.len_utf8())
+    }
+
+    /// Whether the character ends an identifier or number right before it.
+    fn breaks_ident(&self, c: char) -> bool {
+        Self::IDENT_BREAKERS.contains(&c)
+            || self.config.arithmetic_operators && Self::ARITHMETIC_OPERATORS.contains(&c)
+            || self.config.opens_group(c).is_some()
+            || self.config.closes_group(c)
+    }
+
+    /// Whether the character could start an operand of a binary operator.
+    fn starts_operand(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '(' || c == '"'
+    }
+
+    /// Whether the character can be part of an identifier after its first
+    /// character. Letters of any script are fine, but digits must be ASCII,
+    /// the same digits numeric literals use. Other digits, like `٣`, are
+    /// unexpected characters rather than part of the identifier.
+    fn continues_ident(c: char) -> bool {
+        c.is_alphabetic() || c.is_ascii_digit() || c == '_'
+    }
+
+    pub fn advance(&mut self) {
+        let c = self.getc();
+        let char_length = c.map(char::len_utf8).unwrap_or(0);
+        self.input_head += char_length;
+        match c {
+            Some('\n') => {
+                self.current_line += 1;
+                self.current_column = 0;
+                self.at_line_start = true;
+            }
+            // Part of the line break, the `\n` counts the line
+            Some('\r') if self.getc() == Some('\n') => (),
+            Some('\t') => {
+                self.current_column +=
+                    self.config.tab_width - self.current_column % self.config.tab_width;
+            }
+            Some(c) => {
+                self.current_column += 1;
+                self.at_line_start &= Self::WHITESPACE.contains(&c);
+            }
+            None => (),
+        }
+    }
+
+    #[inline]
+    pub fn input_filepath(&self) -> &Path {
+        &self.input_filepath
+    }
+
+    /// Where the current token is in the source.
+    #[inline]
+    pub fn token_span(&self) -> Span {
+        self.token_range.clone().into()
+    }
+
+    /// Moves the lexer to the start of the given line, counting from 1, so
+    /// the next token is the first one of that line.
+    pub fn skip_to_line(&mut self, line: usize) -> LexResult<()> {
+        let (first_line, first_column) = self.config.initial_position;
+        let line_offsets = self.line_offsets();
+        let line_count = line_offsets.len();
+        match line
+            .checked_sub(first_line + 1)
+            .and_then(|i| line_offsets.get(i))
+        {
+            Some(&offset) => {
+                self.input_head = offset;
+                self.current_line = line - 1;
+                self.current_column = if self.current_line == first_line {
+                    first_column
+                } else {
+                    0
+                };
+                self.at_line_start = true;
+                self.state = State::Looking;
+                self.token_range = offset..offset;
+                self.lookahead.clear();
+                self.pending_error = None;
+                self.interpolations.clear();
+                self.conditions.clear();
+                self.open_groups.clear();
+                Ok(())
+            }
+            None => Err(LexicError::LineOutOfRange {
+                file: self.input_filepath.clone(),
+                line,
+                column: 1,
+                offset: self.code.len(),
+                line_count,
+            }),
+        }
+    }
+
+    /// Replaces the text of the given line, counting from 1, and lexes it
+    /// again, returning its tokens. The text should not include the line
+    /// break. Afterwards the lexer goes on from the start of the next line.
+    pub fn relex_line(&mut self, line: usize, text: &str) -> LexResult<Vec<(Token, Span)>> {
+        self.skip_to_line(line)?;
+        let start = self.input_head;
+        let end = start
+            + self
+                .remaining()
+                .find('\n')
+                .unwrap_or(self.remaining().len());
+        self.code.to_mut().replace_range(start..end, text);
+        // The old offsets are wrong past the edited line
+        self.line_offsets.take();
+
+        let end = start + text.len();
+        let mut tokens = Vec::new();
+        loop {
+            // Errors on the lines after are left for the next get_token
+            match self.peek_token() {
+                Ok(Token::EOF) => break Ok(tokens),
+                Ok(_) if self.lookahead[0].range.start >= end => break Ok(tokens),
+                Err(e) if e.offset() >= end => break Ok(tokens),
+                Err(e) => break Err(e),
+                Ok(_) => {
+                    let tok = self.get_token()?;
+                    tokens.push((tok, self.token_span()));
+                }
+            }
+        }
+    }
+
+    /// The number of lines in the source, a line break ends a line. An empty
+    /// source has no lines, and the last line counts even without a line
+    /// break at its end.
+    pub fn count_lines(&self) -> usize {
+        let line_offsets = self.line_offsets();
+        // Safe to unwrap, there is always the offset of the first line
+        let last_line_start = *line_offsets.last().unwrap();
+        line_offsets.len() - 1 + usize::from(last_line_start < self.code.len())
+    }
+
+    /// The `(line, column)` of a byte offset in the source, both counting
+    /// from 1, the same way errors report them. Offsets past the end of the
+    /// source are clamped to the end.
+    pub fn line_col_at(&self, offset: usize) -> (usize, usize) {
+        let (first_line, first_column) = self.config.initial_position;
+        let offset = offset.min(self.code.len());
+        let line = self.line_offsets().partition_point(|&o| o <= offset) - 1;
+        let line_start = self.line_offsets()[line];
+        let line_first_column = if line == 0 { first_column } else { 0 };
+        let column = self.code[line_start..]
+            .char_indices()
+            .take_while(|&(i, _)| line_start + i < offset)
+            .fold(line_first_column, |column, (_, c)| match c {
+                '\t' => column + self.config.tab_width - column % self.config.tab_width,
+                _ => column + 1,
+            });
+        (first_line + line + 1, column + 1)
+    }
+
+    /// The `(line, column)` of each byte offset, the same as
+    /// [`Lexer::line_col_at`] for each of them, in the order given. The
+    /// offsets are sorted first, so each line is only walked once, however
+    /// many offsets it has.
+    pub fn byte_to_positions(&self, offsets: &[usize]) -> Vec<(usize, usize)> {
+        let (first_line, first_column) = self.config.initial_position;
+        let line_offsets = self.line_offsets();
+        let mut order: Vec<_> = (0..offsets.len()).collect();
+        order.sort_unstable_by_key(|&i| offsets[i]);
+        let mut positions = vec![(0, 0); offsets.len()];
+        // Where the walk is, past the characters already counted
+        let (mut line, mut head, mut column) = (0, 0, first_column);
+        for i in order {
+            let offset = offsets[i].min(self.code.len());
+            while line + 1 < line_offsets.len() && line_offsets[line + 1] <= offset {
+                line += 1;
+                head = line_offsets[line];
+                column = 0;
+            }
+            while head < offset {
+                // Safe to unwrap, the head is before the end of the source
+                let c = self.code[head..].chars().next().unwrap();
+                column = match c {
+                    '\t' => column + self.config.tab_width - column % self.config.tab_width,
+                    _ => column + 1,
+                };
+                head += c.len_utf8();
+            }
+            positions[i] = (first_line + line + 1, column + 1);
+        }
+        positions
+    }
+
+    /// The part of the source not yet consumed by the lexer.
+    #[inline]
+    pub fn remaining(&self) -> &str {
+        &self.code[self.input_head..]
+    }
+
+    /// Whether all the source was consumed, so the next token is EOF or an
+    /// error at the end of the input, like an unterminated string. Tokens
+    /// already peeked count as not consumed. Trailing whitespace is only
+    /// consumed when lexing the token after it.
+    #[inline]
+    pub fn is_at_eof(&self) -> bool {
+        self.input_head >= self.code.len() && self.lookahead.is_empty()
+    }
+
+    /// How many bytes of the source the lexer went through, including any
+    /// peeked tokens.
+    #[inline]
+    pub fn bytes_consumed(&self) -> usize {
+        self.input_head
+    }
+
+    /// The fraction of the source consumed so far, from 0.0 to 1.0, meant for
+    /// progress bars. An empty source is always fully consumed.
+    pub fn progress(&self) -> f64 {
+        if self.code.is_empty() {
+            1.0
+        } else {
+            self.input_head as f64 / self.code.len() as f64
+        }
+    }
+
+    /// The text of the current token. Never panics in release builds, a
+    /// broken token range (which would be a bug in the lexer) gives an empty
+    /// string, debug builds assert on it.
+    #[inline]
+    pub fn token_str(&self) -> &str {
+        debug_assert!(
+            self.code.get(self.token_range.clone()).is_some(),
+            "Bad token range {:?}",
+            self.token_range
+        );
+        self.token_str_or_empty()
+    }
+
+    /// The same as [`Lexer::token_str`], without the debug assertion.
+    #[inline]
+    fn token_str_or_empty(&self) -> &str {
+        self.code.get(self.token_range.clone()).unwrap_or("")
+    }
+
+    /// The text of the current token borrowed from a static source, so it
+    /// can be kept after the lexer is gone. `None` unless the lexer was
+    /// created with [`Lexer::from_static`], and the source not edited since.
+    pub fn static_token_str(&self) -> Option<&'static str> {
+        match self.code {
+            Source::Static(code) => code.get(self.token_range.clone()),
+            _ => None,
+        }
+    }
+
+    /// The raw bytes of the current token, the same as the bytes of
+    /// [`Lexer::token_str`]. Never panics, a range out of bounds gives no
+    /// bytes.
+    #[inline]
+    pub fn lexeme_bytes(&self) -> &[u8] {
+        self.code
+            .as_bytes()
+            .get(self.token_range.clone())
+            .unwrap_or_default()
+    }
+
+    /// The text of the current token as the parser should compare it. The
+    /// same as [`Lexer::token_str`], except for identifiers when
+    /// [`LexerConfig::case_insensitive_identifiers`] is enabled, which are
+    /// lowercased.
+    pub fn normalized_lexeme(&self) -> Cow<'_, str> {
+        match self.current_token {
+            Some(Token::Identifier) if self.config.case_insensitive_identifiers => {
+                Cow::Owned(self.token_str().to_lowercase())
+            }
+            _ => Cow::Borrowed(self.token_str()),
+        }
+    }
+
+    #[cfg(test)]
+    fn set_token_range(&mut self, range: Range<usize>) {
+        self.token_range = range;
+    }
+
+    /// The text of the current doc comment, without the doc marker (`##` by
+    /// default). Returns `None` if the current token is not a doc comment.
+    pub fn doc_text(&self) -> Option<&str> {
+        self.token_str()
+            .strip_prefix(self.config.comment_style.doc_marker())
+    }
+
+    /// The text of the current inline code, without the backticks. Returns
+    /// `None` if the current token is not inline code.
+    pub fn inline_code_text(&self) -> Option<&str> {
+        match self.current_token? {
+            Token::InlineCode => self.token_str().strip_prefix('`')?.strip_suffix('`'),
+            _ => None,
+        }
+    }
+
+    /// The number of the current numeric token, that is the token text without
+    /// the `%` of percent literals or the type suffix.
+    #[inline]
+    pub fn numeric_str(&self) -> &str {
+        match self.numeric_suffix() {
+            Some(suffix) => &self.code[self.token_range.start..suffix.start],
+            None => self.token_str().trim_end_matches('%'),
+        }
+    }
+
+    /// Where the type suffix of the current integer or float literal is, as
+    /// the `i32` of `42i32`. Returns `None` if the number has no suffix or the
+    /// current token is not an integer or float literal.
+    pub fn numeric_suffix(&self) -> Option<Span> {
+        match self.current_token? {
+            Token::IntegerLiteral | Token::FloatLiteral => {
+                let suffix_start = Self::suffix_start(self.token_str())?;
+                Some(Span {
+                    start: self.token_range.start + suffix_start,
+                    end: self.token_range.end,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Where the suffix starts in the text of a number, that is, the first
+    /// character that is not a digit or the dot.
+    fn suffix_start(number: &str) -> Option<usize> {
+        number.find(|c: char| !c.is_ascii_digit() && c != '.')
+    }
+
+    /// The value of the current integer or percent literal, as the requested
+    /// integer type. Fails with `LexicError::NumericOverflow` if the value
+    /// does not fit the type, or with `LexicError::UnexpectedToken` if the
+    /// current token is not an integer or percent literal.
+    pub fn numeric_value<T: TryFrom<u64>>(&self) -> LexResult<T> {
+        match self.current_token {
+            Some(Token::IntegerLiteral | Token::PercentLiteral) => (),
+            found => {
+                return Err(LexicError::UnexpectedToken {
+                    expected: Token::IntegerLiteral,
+                    // Nothing lexed yet, or an error, so no token at all
+                    found: found.unwrap_or(Token::EOF),
+                    file: self.input_filepath.clone(),
+                    line: self.token_line() + 1,
+                    column: self.token_column + 1,
+                    offset: self.token_range.start,
+                });
+            }
+        }
+        // The lexer only accepts digits, so the only failure is overflowing
+        self.numeric_str()
+            .parse::<u64>()
+            .ok()
+            .and_then(|value| T::try_from(value).ok())
+            .ok_or_else(|| LexicError::NumericOverflow {
+                file: self.input_filepath.clone(),
+                line: self.token_line() + 1,
+                column: self.token_column + 1,
+                offset: self.token_range.start,
+                lexeme: self.token_str().to_owned(),
+            })
+    }
+
+    /// The value of the current string literal, without the quotes and with
+    /// the escape sequences replaced. Returns `None` if the current token is
+    /// not a string literal or a part of a string with interpolations.
+    ///
+    /// Line breaks inside the string are always decoded as `\n`, so a `\r\n`
+    /// in the source collapses to a single `\n`. The original text is still
+    /// available through [`Lexer::token_str`].
+    pub fn unescaped_string_value(&self) -> Option<String> {
+        let token_str = self.token_str();
+        let content = match self.current_token? {
+            Token::StringLiteral => token_str.strip_prefix('"')?.strip_suffix('"')?,
+            Token::StringStart => token_str.strip_prefix('"')?,
+            Token::StringFragment => token_str,
+            Token::StringEnd => token_str.strip_suffix('"')?,
+            _ => return None,
+        };
+        let mut value = String::with_capacity(content.len());
+        let mut chars = content.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('0') => value.push('\0'),
+                    // Either '\\', '"' or '$', the lexer rejects any other escape
+                    Some(escaped) => value.push(escaped),
+                    None => (),
+                },
+                '\r' if chars.peek() == Some(&'\n') => (),
+                c => value.push(c),
+            }
+        }
+        Some(value)
+    }
+
+    /// The most recent token returned by [`Lexer::get_token`]. Unlike the
+    /// current token it is not cleared by errors, and reaching the end of
+    /// the input does not replace it, so it is still the last real token.
+    #[inline]
+    pub fn last_token(&self) -> Option<Token> {
+        self.last_token
+    }
+
+    /// The warnings found so far. Unlike errors they don't stop lexing, the
+    /// token is still returned.
+    #[inline]
+    pub fn warnings(&self) -> &[LexicWarning] {
+        &self.warnings
+    }
+
+    /// Whether the current token is a keyword once its case is ignored.
+    fn is_case_folded_keyword(&self) -> bool {
+        KEYWORDS
+            .iter()
+            .any(|keyword| keyword.eq_ignore_ascii_case(self.token_str()))
+    }
+
+    /// Whether the current token is the first one on its line, that is, only
+    /// whitespace comes before it on the line.
+    #[inline]
+    pub fn token_starts_line(&self) -> bool {
+        self.token_starts_line
+    }
+
+    /// The column where the current token starts, counting from zero. Tabs
+    /// are expanded according to the tab width.
+    #[inline]
+    pub fn token_start_column(&self) -> usize {
+        self.token_column
+    }
+
+    /// The line of the current token, counting from zero.
+    fn token_line(&self) -> usize {
+        let line = self
+            .line_offsets()
+            .partition_point(|&offset| offset <= self.token_range.start)
+            - 1;
+        self.config.initial_position.0 + line
+    }
+
+    /// Whether a directive starts here, that is an `#if` or `#endif` with
+    /// only whitespace before it on the line.
+    fn at_directive(&self) -> bool {
+        let starts_with_word = |word: &str| {
+            self.remaining()
+                .strip_prefix(word)
+                .is_some_and(|rest| rest.chars().next().is_none_or(char::is_whitespace))
+        };
+        self.config.directives
+            && self.at_line_start
+            && (starts_with_word("#if") || starts_with_word("#endif"))
+    }
+
+    /// Handles the directive at the current position, leaving the lexer at
+    /// the end of its line.
+    fn directive(&mut self) -> LexResult<()> {
+        let (line, column, offset) = (self.current_line, self.current_column, self.input_head);
+        let line_length = self
+            .remaining()
+            .find('\n')
+            .unwrap_or(self.remaining().len());
+        let words: Vec<_> = self.remaining()[..line_length].split_whitespace().collect();
+        match words[..] {
+            ["#if", flag] => {
+                let parent_active = self.conditions.last().is_none_or(|c| c.0);
+                let active = parent_active && self.config.defined_flags.contains(flag);
+                self.conditions.push((active, line, column, offset));
+            }
+            ["#endif"] if !self.conditions.is_empty() => {
+                self.conditions.pop();
+            }
+            ["#endif"] => {
+                return Err(LexicError::UnmatchedEndif {
+                    file: self.input_filepath.clone(),
+                    line: line + 1,
+                    column: column + 1,
+                    offset,
+                })
+            }
+            _ => {
+                return Err(LexicError::InvalidDirective {
+                    file: self.input_filepath.clone(),
+                    line: line + 1,
+                    column: column + 1,
+                    offset,
+                })
+            }
+        }
+        while self.getc().is_some_and(|c| c != '\n') {
+            self.advance();
+        }
+        Ok(())
+    }
+
+    /// Skips the lines excluded by an `#if`, up to the `#endif` that closes
+    /// it. Does nothing if the current lines are lexed.
+    fn skip_excluded(&mut self) -> LexResult<()> {
+        while self.conditions.last().is_some_and(|c| !c.0) {
+            // To the start of the next line, the end of the input is an
+            // unterminated `#if`, reported when reaching it
+            while let Some(c) = self.getc() {
+                self.advance();
+                if c == '\n' {
+                    break;
+                }
+            }
+            while self
+                .getc()
+                .is_some_and(|c| Self::WHITESPACE.contains(&c) && c != '\n')
+            {
+                self.advance();
+            }
+            if self.getc().is_none() {
+                break;
+            }
+            if self.at_directive() {
+                self.directive()?;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn at_comment_start(&self) -> bool {
+        self.remaining()
+            .starts_with(self.config.comment_style.introducer())
+    }
+
+    fn err_unexpected_char(&self, c: char) -> LexicError {
+        LexicError::UnexpectedCharacter {
+            c,
+            file: self.input_filepath.clone(),
+            line: self.current_line + 1,
+            column: self.current_column + 1,
+            offset: self.input_head,
+        }
+    }
+
+    fn err_unknown_escape(&self, c: char) -> LexicError {
+        LexicError::UnknownEscapeSequence {
+            c,
+            file: self.input_filepath.clone(),
+            line: self.current_line + 1,
+            column: self.current_column + 1,
+            offset: self.input_head,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum LexicError {
+    UnexpectedCharacter {
+        c: char,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    UnexpectedIdentationLevel {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    UnterminatedString {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    UnknownEscapeSequence {
+        c: char,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    NullByte {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    InvalidUtf8 {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// Not an error in the source itself, a line was requested past the end.
+    LineOutOfRange {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+        line_count: usize,
+    },
+    /// Not an error in the source itself, marks where error recovery gave up.
+    TooManyErrors {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+        limit: usize,
+    },
+    /// A different token than the expected one, see [`Lexer::expect`].
+    UnexpectedToken {
+        expected: Token,
+        found: Token,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// A number too large for the requested type, see
+    /// [`Lexer::numeric_value`].
+    NumericOverflow {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+        lexeme: String,
+    },
+    /// Punctuation from other languages, such as `:=`, with the one to use
+    /// instead.
+    ReservedPunctuation {
+        found: &'static str,
+        suggestion: &'static str,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    UnterminatedInlineCode {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// The source could not be read. Reported at the start of the file.
+    Io {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+        error: Arc<io::Error>,
+    },
+    /// An `#endif` without an `#if` before it.
+    UnmatchedEndif {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// An `#if` without an `#endif` after it.
+    UnterminatedIf {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// A directive line that is not `#if FLAG` or `#endif`.
+    InvalidDirective {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// A byte offset past the end of the source or inside a character.
+    InvalidOffset {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// A group closed with the wrong delimiter, as the `)` of `{ )`.
+    MismatchedGroupClose {
+        found: char,
+        /// The delimiter closing the innermost open group
+        expected: char,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// An identifier right before a string, as in `foo"bar"`. Only reported
+    /// when enabled with [`LexerConfig::adjacent_string_errors`].
+    IdentifierBeforeString {
+        identifier: String,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+    /// A string literal longer than [`LexerConfig::max_string_len`]. The
+    /// position is where the string starts.
+    StringTooLong {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+        /// The longest a string can be, in bytes
+        max: usize,
+    },
+}
+
+/// The explanation of each error code, see [`LexicError::explain`].
+const EXPLANATIONS: &[(&str, &str)] = &[
+    ("PLR0001", "A character that starts no token was found, outside of strings and comments.\n\nFor example:\n\n    let price = 10 @ 2\n\nThe `@` is not part of the language, remove it or use an operator.\n"),
+    ("PLR0002", "A line is idented to a level that matches no enclosing block.\n\nIndent the line by the same amount as a line before it.\n"),
+    ("PLR0003", "A string literal is not closed before the end of the file.\n\nFor example:\n\n    let name = \"unfinished\n\nAdd the closing `\"`. The error points to where the string starts.\n"),
+    ("PLR0004", "A `\\` in a string is followed by a character with no escape meaning.\n\nFor example:\n\n    \"C:\\windows\"\n\nOnly `\\\\`, `\\\"`, `\\$`, `\\n`, `\\t`, `\\r` and `\\0` are escapes, write `\\\\` for a literal `\\`.\n"),
+    ("PLR0005", "A null byte was found outside of a string literal, most likely a binary file being lexed by mistake.\n\nCheck that the right file is being lexed.\n"),
+    ("PLR0006", "The source is not valid UTF-8.\n\nSave the file as UTF-8. The error points to the first invalid byte.\n"),
+    ("PLR0007", "A line was asked for past the last line of the source.\n\nOnly lines from 1 up to the number of lines of the source can be lexed again.\n"),
+    ("PLR0008", "Lexing stopped after too many errors, see `--max-errors`.\n\nFix the errors reported before this one, or raise the limit.\n"),
+    ("PLR0009", "A token other than the one expected was found.\n\nReported when checking for a token, as the end of the input.\n"),
+    ("PLR0010", "A number literal does not fit in the requested integer type.\n\nFor example:\n\n    let big = 99999999999999999999\n\nUse a smaller number.\n"),
+    ("PLR0011", "Punctuation from other languages with no meaning here was found.\n\nFor example:\n\n    x := 1\n\nUse the suggested punctuation instead, `=` for assignments.\n"),
+    ("PLR0012", "Inline code between backticks is not closed before the end of the file. Only reported with `LexerConfig::inline_code` enabled, otherwise a backtick is an unexpected character.\n\nFor example:\n\n    see `code\n\nAdd the closing backtick.\n"),
+    ("PLR0013", "The source file could not be read.\n\nCheck that the file exists and can be read.\n"),
+    ("PLR0014", "An `#endif` has no `#if` before it.\n\nFor example:\n\n    #endif\n\nRemove the `#endif`, or add the `#if` it closes.\n"),
+    ("PLR0015", "An `#if` is not closed by an `#endif` before the end of the file.\n\nFor example:\n\n    #if DEBUG\n    log\n\nAdd the `#endif`. The error points to the `#if`.\n"),
+    ("PLR0016", "A line starting with `#if` or `#endif` is neither `#if FLAG` nor `#endif` alone.\n\nFor example:\n\n    #if DEBUG RELEASE\n\nOnly `#if FLAG` and `#endif` are directives.\n"),
+    ("PLR0017", "A byte offset is past the end of the source or in the middle of a character.\n\nOffsets must be at character boundaries, as the ones of token spans.\n"),
+    ("PLR0018", "A group is closed with a delimiter other than the one matching its opening. Delimiters other than `(` and `)` are set with `LexerConfig::group_delimiters`.\n\nFor example:\n\n    call(a]\n\nClose the group with the delimiter matching the one that opened it.\n"),
+    ("PLR0019", "An identifier is right before a string, with nothing between them.\n\nFor example:\n\n    print\"hello\"\n\nAdd a space or an operator between them. There are no string prefixes.\n"),
+    ("PLR0020", "A string literal is longer than the maximum configured.\n\nSplit the string, or raise the limit.\n"),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl LexicError {
+    pub fn severity(&self) -> Severity {
+        use LexicError::*;
+        match self {
+            UnexpectedCharacter { .. }
+            | UnexpectedIdentationLevel { .. }
+            | UnterminatedString { .. }
+            | UnknownEscapeSequence { .. }
+            | NullByte { .. }
+            | InvalidUtf8 { .. }
+            | LineOutOfRange { .. }
+            | TooManyErrors { .. }
+            | UnexpectedToken { .. }
+            | NumericOverflow { .. }
+            | ReservedPunctuation { .. }
+            | UnterminatedInlineCode { .. }
+            | Io { .. }
+            | UnmatchedEndif { .. }
+            | UnterminatedIf { .. }
+            | InvalidDirective { .. }
+            | InvalidOffset { .. }
+            | MismatchedGroupClose { .. }
+            | IdentifierBeforeString { .. }
+            | StringTooLong { .. } => Severity::Error,
+        }
+    }
+
+    /// A stable code for the kind of error, as `PLR0001`, explained by
+    /// [`LexicError::explain`].
+    pub fn code(&self) -> &'static str {
+        use LexicError::*;
+        match self {
+            UnexpectedCharacter { .. } => "PLR0001",
+            UnexpectedIdentationLevel { .. } => "PLR0002",
+            UnterminatedString { .. } => "PLR0003",
+            UnknownEscapeSequence { .. } => "PLR0004",
+            NullByte { .. } => "PLR0005",
+            InvalidUtf8 { .. } => "PLR0006",
+            LineOutOfRange { .. } => "PLR0007",
+            TooManyErrors { .. } => "PLR0008",
+            UnexpectedToken { .. } => "PLR0009",
+            NumericOverflow { .. } => "PLR0010",
+            ReservedPunctuation { .. } => "PLR0011",
+            UnterminatedInlineCode { .. } => "PLR0012",
+            Io { .. } => "PLR0013",
+            UnmatchedEndif { .. } => "PLR0014",
+            UnterminatedIf { .. } => "PLR0015",
+            InvalidDirective { .. } => "PLR0016",
+            InvalidOffset { .. } => "PLR0017",
+            MismatchedGroupClose { .. } => "PLR0018",
+            IdentifierBeforeString { .. } => "PLR0019",
+            StringTooLong { .. } => "PLR0020",
+        }
+    }
+
+    /// A longer description of the error with a code, with an example and
+    /// how to fix it. `None` if no error has that code.
+    pub fn explain(code: &str) -> Option<&'static str> {
+        EXPLANATIONS
+            .iter()
+            .find(|&&(known, _)| known == code)
+            .map(|&(_, explanation)| explanation)
+    }
+
+    /// The `(line, column)` where the error happened.
+    pub fn position(&self) -> (usize, usize) {
+        use LexicError::*;
+        match self {
+            UnexpectedCharacter { line, column, .. }
+            | UnexpectedIdentationLevel { line, column, .. }
+            | UnterminatedString { line, column, .. }
+            | UnknownEscapeSequence { line, column, .. }
+            | NullByte { line, column, .. }
+            | InvalidUtf8 { line, column, .. }
+            | LineOutOfRange { line, column, .. }
+            | TooManyErrors { line, column, .. }
+            | UnexpectedToken { line, column, .. }
+            | NumericOverflow { line, column, .. }
+            | ReservedPunctuation { line, column, .. }
+            | UnterminatedInlineCode { line, column, .. }
+            | Io { line, column, .. }
+            | UnmatchedEndif { line, column, .. }
+            | UnterminatedIf { line, column, .. }
+            | InvalidDirective { line, column, .. }
+            | InvalidOffset { line, column, .. }
+            | MismatchedGroupClose { line, column, .. }
+            | IdentifierBeforeString { line, column, .. }
+            | StringTooLong { line, column, .. } => (*line, *column),
+        }
+    }
+
+    /// Orders errors by their position in the source, line first, then
+    /// column. Meant for sorting diagnostics, as in
+    /// `errors.sort_by(LexicError::cmp_position)`.
+    pub fn cmp_position(&self, other: &Self) -> std::cmp::Ordering {
+        self.position().cmp(&other.position())
+    }
+
+    /// Returns a displayable version of the error with the path separators
+    /// normalized to `/`, so the messages are the same on every platform.
+    #[inline]
+    pub fn display_normalized(&self) -> NormalizedDisplay<'_> {
+        NormalizedDisplay(self)
+    }
+
+    fn file(&self) -> &Path {
+        use LexicError::*;
+        match self {
+            UnexpectedCharacter { file, .. }
+            | UnexpectedIdentationLevel { file, .. }
+            | UnterminatedString { file, .. }
+            | UnknownEscapeSequence { file, .. }
+            | NullByte { file, .. }
+            | InvalidUtf8 { file, .. }
+            | LineOutOfRange { file, .. }
+            | TooManyErrors { file, .. }
+            | UnexpectedToken { file, .. }
+            | NumericOverflow { file, .. }
+            | ReservedPunctuation { file, .. }
+            | UnterminatedInlineCode { file, .. }
+            | Io { file, .. }
+            | UnmatchedEndif { file, .. }
+            | UnterminatedIf { file, .. }
+            | InvalidDirective { file, .. }
+            | InvalidOffset { file, .. }
+            | MismatchedGroupClose { file, .. }
+            | IdentifierBeforeString { file, .. }
+            | StringTooLong { file, .. } => file,
+        }
+    }
+
+    /// The byte offset in the source where the error happened.
+    pub fn offset(&self) -> usize {
+        use LexicError::*;
+        match self {
+            UnexpectedCharacter { offset, .. }
+            | UnexpectedIdentationLevel { offset, .. }
+            | UnterminatedString { offset, .. }
+            | UnknownEscapeSequence { offset, .. }
+            | NullByte { offset, .. }
+            | InvalidUtf8 { offset, .. }
+            | LineOutOfRange { offset, .. }
+            | TooManyErrors { offset, .. }
+            | UnexpectedToken { offset, .. }
+            | NumericOverflow { offset, .. }
+            | ReservedPunctuation { offset, .. }
+            | UnterminatedInlineCode { offset, .. }
+            | Io { offset, .. }
+            | UnmatchedEndif { offset, .. }
+            | UnterminatedIf { offset, .. }
+            | InvalidDirective { offset, .. }
+            | InvalidOffset { offset, .. }
+            | MismatchedGroupClose { offset, .. }
+            | IdentifierBeforeString { offset, .. }
+            | StringTooLong { offset, .. } => *offset,
+        }
+    }
+
+    /// The description of the error, without the location prefix.
+    pub fn message(&self) -> String {
+        use LexicError::*;
+        match self {
+            UnexpectedCharacter { c, .. } => format!("Unexpected character '{}'", c),
+            UnexpectedIdentationLevel { .. } => "Unexpected identation level".to_owned(),
+            UnterminatedString { .. } => "Unterminated string literal".to_owned(),
+            UnknownEscapeSequence { c, .. } => format!("Unknown escape sequence '\\{}'", c),
+            NullByte { .. } => "Null byte, is this a binary file?".to_owned(),
+            InvalidUtf8 { .. } => "Invalid UTF-8".to_owned(),
+            LineOutOfRange {
+                line, line_count, ..
+            } => {
+                format!(
+                    "Line {} is out of range, there are {} lines",
+                    line, line_count
+                )
+            }
+            TooManyErrors { limit, .. } => {
+                format!("Too many errors, stopped after {}", limit)
+            }
+            UnexpectedToken {
+                expected, found, ..
+            } => format!("Expected {:?}, found {:?}", expected, found),
+            NumericOverflow { lexeme, .. } => format!("Number {} is too large", lexeme),
+            ReservedPunctuation {
+                found, suggestion, ..
+            } => format!("Unexpected '{}', did you mean '{}'?", found, suggestion),
+            UnterminatedInlineCode { .. } => "Unterminated inline code".to_owned(),
+            Io { error, .. } => format!("Could not read the file: {}", error),
+            UnmatchedEndif { .. } => "#endif without a matching #if".to_owned(),
+            UnterminatedIf { .. } => "#if without a matching #endif".to_owned(),
+            InvalidDirective { .. } => {
+                "Invalid directive, expected '#if FLAG' or '#endif'".to_owned()
+            }
+            InvalidOffset { offset, .. } => {
+                format!("Offset {} is past the end or inside a character", offset)
+            }
+            MismatchedGroupClose {
+                found, expected, ..
+            } => format!("Mismatched '{}', expected '{}'", found, expected),
+            IdentifierBeforeString { identifier, .. } => format!(
+                "'{}' is not a string prefix, expected a separator or an operator before the string",
+                identifier
+            ),
+            StringTooLong { max, .. } => format!("String longer than {} bytes", max),
+        }
+    }
+
+    /// Converts the error to a JSON object with its location, message and
+    /// severity, meant for tools such as IDEs.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let (line, column) = self.position();
+        serde_json::json!({
+            "file": self.file().to_string_lossy(),
+            "line": line,
+            "column": column,
+            "offset": self.offset(),
+            "message": self.message(),
+            "code": self.code(),
+            "severity": self.severity().to_string(),
+        })
+    }
+
+    fn io(file: &Path, error: io::Error) -> Self {
+        LexicError::Io {
+            file: file.to_path_buf(),
+            line: 1,
+            column: 1,
+            offset: 0,
+            error: Arc::new(error),
+        }
+    }
+
+    fn fmt_with_file(&self, f: &mut Formatter<'_>, file: &dyn Display) -> std::fmt::Result {
+        match self {
+            // Not about any line in particular
+            LexicError::Io { .. } => write!(f, "{}: {}", file, self.message()),
+            // The `file:line:column` prefix is what editors look for to
+            // jump to the error
+            _ => {
+                let (line, column) = self.position();
+                write!(f, "{}:{}:{}: {}", file, line, column, self.message())
+            }
+        }
+    }
+}
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+impl Error for LexicError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LexicError::Io { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+impl Display for LexicError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_file(f, &self.file().display())
+    }
+}
+
+/// Something suspicious in the source that is still valid, reported by
+/// [`Lexer::warnings`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LexicWarning {
+    /// An identifier that is only a keyword because the case of keywords is
+    /// ignored, see [`LexerConfig::case_insensitive_keywords`].
+    CaseFoldedKeyword {
+        lexeme: String,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        offset: usize,
+    },
+}
+
+impl LexicWarning {
+    /// The `(line, column)` of the warning, both counting from 1.
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            LexicWarning::CaseFoldedKeyword { line, column, .. } => (*line, *column),
+        }
+    }
+}
+
+impl Display for LexicWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexicWarning::CaseFoldedKeyword {
+                lexeme,
+                file,
+                line,
+                column,
+                ..
+            } => write!(
+                f,
+                "{}:{}:{}: {}: '{}' is taken as the keyword '{}'",
+                file.display(),
+                line,
+                column,
+                Severity::Warning,
+                lexeme,
+                lexeme.to_ascii_lowercase()
+            ),
+        }
+    }
+}
+
+/// Displays a [`LexicError`] using `/` as the path separator regardless of the
+/// platform. See [`LexicError::display_normalized`].
+pub struct NormalizedDisplay<'a>(&'a LexicError);
+
+impl Display for NormalizedDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let file = self.0.file().to_string_lossy().replace('\\', "/");
+        self.0.fmt_with_file(f, &file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_tokens;
+
+    /// Reads a fixture, failing with the path when it can't, so a missing
+    /// fixture is easy to tell apart from a lexer bug.
+    fn read_fixture(path: &str) -> String {
+        match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => panic!("Could not read the fixture {}: {}", path, e),
+        }
+    }
+
+    #[test]
+    fn simple_1() {
+        let path = "input_examples/simple1.plr";
+        let mut lexer = Lexer::from_source(path, read_fixture(path));
+        let expected_content = read_fixture("util_files/test_data/lexer_output/simple1.plr.txt");
+        for expected_line in expected_content.lines() {
+            let tok = lexer.get_token().unwrap();
+            let line = format!(
+                "{:?} \"{}\", starts at col: {}",
+                tok,
+                lexer.token_str(),
+                lexer.token_start_column()
+            );
+            assert_eq!(line, expected_line, "Wrong token parsing");
+            if tok == Token::EOF {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Could not read the fixture input_examples/missing.plr")]
+    fn missing_fixture() {
+        read_fixture("input_examples/missing.plr");
+    }
+
+    #[test]
+    fn display_normalized_path() {
+        let error = LexicError::UnexpectedCharacter {
+            c: '$',
+            file: PathBuf::from("C:\\code\\examples\\simple1.plr"),
+            line: 3,
+            column: 7,
+            offset: 42,
+        };
+        assert_eq!(
+            error.display_normalized().to_string(),
+            "C:/code/examples/simple1.plr:3:7: Unexpected character '$'"
+        );
+    }
+
+    #[test]
+    fn comment_styles() {
+        let fixtures = [
+            (CommentStyle::Hash, "input_examples/comments.plr"),
+            (
+                CommentStyle::DoubleSlash,
+                "input_examples/comments_double_slash.plr",
+            ),
+            (
+                CommentStyle::DoubleDash,
+                "input_examples/comments_double_dash.plr",
+            ),
+        ];
+        let expected = [
+            (Token::DocComment, Some(" Documents the function below")),
+            (Token::Comment, None),
+            (Token::DocComment, Some(" More documentation")),
+            (Token::Identifier, None),
+            (Token::ParensOpen, None),
+            (Token::ParensClose, None),
+            (Token::Colon, None),
+            (Token::Comment, None),
+            (Token::Identifier, None),
+            (Token::DocComment, Some("")),
+            (Token::EOF, None),
+        ];
+        for (style, path) in fixtures {
+            let mut lexer = LexerConfig::default()
+                .comment_style(style)
+                .preserve_trivia(true)
+                .open(path)
+                .unwrap();
+            for (expected_tok, expected_doc_text) in expected {
+                let tok = lexer.get_token().unwrap();
+                assert_eq!(tok, expected_tok, "Wrong token with {:?}", style);
+                assert_eq!(lexer.doc_text(), expected_doc_text);
+                if tok == Token::Comment {
+                    assert!(lexer.token_str().starts_with(style.introducer()));
+                }
+            }
+        }
+
+        // A comment introducer from another style is not a comment
+        let mut lexer = LexerConfig::default()
+            .comment_style(CommentStyle::DoubleSlash)
+            .build("comments.plr", "a # b");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.get_token().unwrap(), Token::Hash);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let mut lexer = LexerConfig::default()
+            .preserve_trivia(true)
+            .open("input_examples/comments.plr")
+            .unwrap();
+        let expected = [
+            (Token::DocComment, "## Documents the function below"),
+            (Token::Comment, "# Just a comment"),
+            (Token::DocComment, "## More documentation"),
+            (Token::Identifier, "function"),
+            (Token::ParensOpen, "("),
+            (Token::ParensClose, ")"),
+            (Token::Colon, ":"),
+            (Token::Comment, "# trailing # comment"),
+            (Token::Identifier, "body"),
+            (Token::DocComment, "##"),
+            (Token::EOF, ""),
+        ];
+        for (expected_tok, expected_str) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!((tok, lexer.token_str()), (expected_tok, expected_str));
+            if tok == Token::DocComment {
+                let doc_text = lexer.doc_text().unwrap();
+                assert!(!doc_text.starts_with('#'));
+            }
+        }
+    }
+
+    #[test]
+    fn comments_skipped_by_default() {
+        let mut lexer = Lexer::new("input_examples/comments.plr").unwrap();
+        let expected = [
+            Token::Identifier,
+            Token::ParensOpen,
+            Token::ParensClose,
+            Token::Colon,
+            Token::Identifier,
+            Token::EOF,
+        ];
+        for expected_tok in expected {
+            assert_eq!(lexer.get_token().unwrap(), expected_tok);
+        }
+    }
+
+    #[test]
+    fn sort_errors_by_position() {
+        let unexpected_char = |line, column| LexicError::UnexpectedCharacter {
+            c: '$',
+            file: PathBuf::from("errors.plr"),
+            line,
+            column,
+            offset: 0,
+        };
+        let mut errors = [
+            unexpected_char(3, 1),
+            LexicError::UnexpectedIdentationLevel {
+                file: PathBuf::from("errors.plr"),
+                line: 2,
+                column: 5,
+                offset: 0,
+            },
+            unexpected_char(1, 9),
+            unexpected_char(2, 4),
+        ];
+        errors.sort_by(LexicError::cmp_position);
+        let positions: Vec<_> = errors.iter().map(LexicError::position).collect();
+        assert_eq!(positions, [(1, 9), (2, 4), (2, 5), (3, 1)]);
+        assert!(errors.iter().all(|e| e.severity() == Severity::Error));
+    }
+
+    #[test]
+    fn string_literals() {
+        let mut lexer =
+            Lexer::from_source("strings.plr", r#"say("hello \"world\"\n" "" "tab\there")"#);
+        let expected = [
+            (Token::Identifier, "say", None),
+            (Token::ParensOpen, "(", None),
+            (
+                Token::StringLiteral,
+                r#""hello \"world\"\n""#,
+                Some("hello \"world\"\n"),
+            ),
+            (Token::StringLiteral, r#""""#, Some("")),
+            (Token::StringLiteral, r#""tab\there""#, Some("tab\there")),
+            (Token::ParensClose, ")", None),
+            (Token::EOF, "", None),
+        ];
+        for (expected_tok, expected_str, expected_value) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!((tok, lexer.token_str()), (expected_tok, expected_str));
+            assert_eq!(lexer.unescaped_string_value().as_deref(), expected_value);
+        }
+    }
+
+    #[test]
+    fn string_literal_errors() {
+        let mut lexer = Lexer::from_source("strings.plr", "ok\n  \"never closed");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnterminatedString {
+                line: 2,
+                column: 3,
+                ..
+            })
+        ));
+
+        let mut lexer = Lexer::from_source("strings.plr", r#""bad \q escape""#);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnknownEscapeSequence {
+                c: 'q',
+                line: 1,
+                column: 7,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn crlf_inside_string_literal() {
+        let mut lexer = Lexer::new("input_examples/crlf_string.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.get_token().unwrap(), Token::StringLiteral);
+        assert_eq!(lexer.token_str(), "\"first line\r\nsecond line\r\n\"");
+        assert_eq!(
+            lexer.unescaped_string_value().unwrap(),
+            "first line\nsecond line\n"
+        );
+    }
+
+    #[test]
+    fn from_source_matches_new() {
+        let path = "input_examples/simple1.plr";
+        let mut from_file = Lexer::new(path).unwrap();
+        let mut from_source = Lexer::from_source(path, std::fs::read_to_string(path).unwrap());
+        loop {
+            let tok = from_file.get_token().unwrap();
+            assert_eq!(from_source.get_token().unwrap(), tok);
+            assert_eq!(from_source.token_str(), from_file.token_str());
+            assert_eq!(
+                from_source.token_start_column(),
+                from_file.token_start_column()
+            );
+            if tok == Token::EOF {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn null_byte() {
+        let mut lexer = Lexer::new("input_examples/null_byte.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.get_token().unwrap(), Token::StringLiteral);
+        assert_eq!(lexer.unescaped_string_value().unwrap(), "in\0string");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        let error = lexer.get_token().unwrap_err();
+        assert!(matches!(
+            error,
+            LexicError::NullByte {
+                line: 2,
+                column: 4,
+                offset: 25,
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "input_examples/null_byte.plr:2:4: Null byte, is this a binary file?"
+        );
+    }
+
+    #[test]
+    fn remaining_shrinks() {
+        let mut lexer = Lexer::from_source("remaining.plr", "first(second): third");
+        assert_eq!(lexer.remaining(), "first(second): third");
+        let expected_remaining = ["(second): third", "second): third", "): third", ": third"];
+        for expected in expected_remaining {
+            lexer.get_token().unwrap();
+            assert_eq!(lexer.remaining(), expected);
+        }
+        lexer.get_token().unwrap();
+        assert_eq!(lexer.remaining(), " third");
+        lexer.get_token().unwrap();
+        assert_eq!(lexer.remaining(), "");
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+        assert_eq!(lexer.remaining(), "");
+    }
+
+    #[test]
+    fn token_str_bad_range() {
+        let mut lexer = Lexer::from_source("bad_range.plr", "ファントム");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        lexer.set_token_range(0..6);
+        assert_eq!(lexer.token_str(), "ファ");
+        // Not on a char boundary
+        lexer.set_token_range(1..3);
+        assert_eq!(lexer.token_str_or_empty(), "");
+        // Out of bounds
+        lexer.set_token_range(0..1000);
+        assert_eq!(lexer.token_str_or_empty(), "");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Bad token range 1..3")]
+    fn token_str_bad_range_asserts() {
+        let mut lexer = Lexer::from_source("bad_range.plr", "ファントム");
+        lexer.set_token_range(1..3);
+        lexer.token_str();
+    }
+
+    #[test]
+    fn underscore() {
+        let mut lexer = Lexer::new("input_examples/underscore.plr").unwrap();
+        let expected = [
+            (Token::Underscore, "_"),
+            (Token::Identifier, "_x"),
+            (Token::Identifier, "x_"),
+            (Token::Identifier, "__"),
+            (Token::ParensOpen, "("),
+            (Token::Underscore, "_"),
+            (Token::ParensClose, ")"),
+            (Token::EOF, ""),
+        ];
+        for (expected_tok, expected_str) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!((tok, lexer.token_str()), (expected_tok, expected_str));
+        }
+    }
+
+    #[test]
+    fn group_markers_around_parens() {
+        let code = "call(a (b)) ()";
+        let mut lexer = LexerConfig::default()
+            .emit_group_markers(true)
+            .build("groups.plr", code);
+        let expected = [
+            (Token::Identifier, "call", 0),
+            (Token::ParensOpen, "(", 4),
+            (Token::GroupBegin, "", 5),
+            (Token::Identifier, "a", 5),
+            (Token::ParensOpen, "(", 7),
+            (Token::GroupBegin, "", 8),
+            (Token::Identifier, "b", 8),
+            (Token::GroupEnd, "", 9),
+            (Token::ParensClose, ")", 9),
+            (Token::GroupEnd, "", 10),
+            (Token::ParensClose, ")", 10),
+            (Token::ParensOpen, "(", 12),
+            (Token::GroupBegin, "", 13),
+            (Token::GroupEnd, "", 13),
+            (Token::ParensClose, ")", 13),
+            (Token::EOF, "", 14),
+        ];
+        for (expected_tok, expected_str, expected_column) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!(
+                (tok, lexer.token_str(), lexer.token_start_column()),
+                (expected_tok, expected_str, expected_column)
+            );
+        }
+
+        let mut lexer = Lexer::from_source("groups.plr", code);
+        loop {
+            let tok = lexer.get_token().unwrap();
+            assert!(!matches!(tok, Token::GroupBegin | Token::GroupEnd));
+            if tok == Token::EOF {
+                break;
+            }
+        }
+
+        // A stray close is left unmatched, without a GroupEnd
+        let mut lexer = LexerConfig::default()
+            .emit_group_markers(true)
+            .build("groups.plr", ") (a))");
+        assert_tokens(
+            &mut lexer,
+            &[
+                (Token::ParensClose, ")"),
+                (Token::ParensOpen, "("),
+                (Token::GroupBegin, ""),
+                (Token::Identifier, "a"),
+                (Token::GroupEnd, ""),
+                (Token::ParensClose, ")"),
+                (Token::ParensClose, ")"),
+            ],
+        );
+    }
+
+    #[test]
+    fn token_histogram() {
+        let source = std::fs::read_to_string("input_examples/simple1.plr").unwrap();
+        let counts = histogram(&source).unwrap();
+        assert_eq!(counts[&Token::Identifier], 22);
+        assert_eq!(counts[&Token::Underscore], 1);
+        assert_eq!(counts[&Token::ParensOpen], 3);
+        assert_eq!(counts[&Token::ParensClose], 3);
+        assert_eq!(counts[&Token::Colon], 1);
+        assert!(!counts.contains_key(&Token::EOF));
+
+        assert!(histogram("").unwrap().is_empty());
+        assert!(histogram("bad @").is_err());
+    }
+
+    #[test]
+    fn tokenize_all_recovers() {
+        let mut lexer = Lexer::from_source("recover.plr", "a @ b\"\\q\"c; d");
+        let tokenized = lexer.tokenize_all();
+        let lexemes: Vec<_> = tokenized
+            .ranges
+            .iter()
+            .map(|range| &lexer.code[range.clone()])
+            .collect();
+        assert_eq!(
+            tokenized.tokens,
+            [
+                Token::Identifier,
+                Token::Identifier,
+                Token::StringLiteral,
+                Token::Identifier
+            ]
+        );
+        assert_eq!(lexemes, ["a", "b", "\"\\q\"", "d"]);
+        let positions: Vec<_> = tokenized.errors.iter().map(LexicError::position).collect();
+        assert_eq!(positions, [(1, 3), (1, 8), (1, 11)]);
+    }
+
+    #[test]
+    fn take_errors() {
+        let mut lexer = Lexer::from_source("take.plr", "a @ b ; c\nd ; e");
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "c");
+        let positions: Vec<_> = lexer
+            .take_errors()
+            .iter()
+            .map(LexicError::position)
+            .collect();
+        assert_eq!(positions, [(1, 3), (1, 7)]);
+        assert!(lexer.take_errors().is_empty());
+
+        // Lexing goes on, with the next errors kept for the next call
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "e");
+        assert_eq!(lexer.get_token_recovering(), Token::EOF);
+        let errors = lexer.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position(), (2, 3));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut lexer = Lexer::from_source("diagnostics.plr", "a @ b ; c");
+        assert!(lexer.diagnostics().is_empty());
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.get_token_recovering(), Token::Identifier);
+        assert_eq!(lexer.diagnostics().len(), 1);
+        while lexer.get_token_recovering() != Token::EOF {}
+        let positions: Vec<_> = lexer
+            .diagnostics()
+            .iter()
+            .map(LexicError::position)
+            .collect();
+        assert_eq!(positions, [(1, 3), (1, 7)]);
+        // Borrowing leaves them there, taking does not
+        assert_eq!(lexer.take_errors().len(), 2);
+        assert!(lexer.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn tokenize_all_max_errors() {
+        let mut lexer = LexerConfig::default()
+            .max_errors(3)
+            .build("max_errors.plr", "@ @ ok @ @ @ @");
+        let tokenized = lexer.tokenize_all();
+        assert_eq!(tokenized.tokens, [Token::Identifier]);
+        assert_eq!(tokenized.errors.len(), 4);
+        assert!(tokenized.errors[..3]
+            .iter()
+            .all(|e| matches!(e, LexicError::UnexpectedCharacter { c: '@', .. })));
+        assert!(matches!(
+            tokenized.errors[3],
+            LexicError::TooManyErrors {
+                limit: 3,
+                column: 10,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn tokens_and_spans_are_ordered() {
+        let tokens: std::collections::BTreeSet<_> = [
+            Token::EOF,
+            Token::Colon,
+            Token::Identifier,
+            Token::Colon,
+            Token::ParensOpen,
+        ]
+        .into_iter()
+        .collect();
+        let tokens: Vec<_> = tokens.into_iter().collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Identifier,
+                Token::Colon,
+                Token::ParensOpen,
+                Token::EOF
+            ]
+        );
+
+        let mut lexer = Lexer::from_source("spans.plr", "ab (c)");
+        let mut spans = std::collections::BTreeSet::new();
+        while lexer.get_token().unwrap() != Token::EOF {
+            spans.insert(lexer.token_span());
+        }
+        spans.insert(Span::from(0..1));
+        let ranges: Vec<_> = spans.into_iter().map(Span::range).collect();
+        assert_eq!(ranges, [0..1, 0..2, 3..4, 4..5, 5..6]);
+    }
+
+    #[test]
+    fn block_colon() {
+        let expected = [
+            Token::Keyword,
+            Token::Identifier,
+            Token::BlockColon,
+            Token::Identifier,
+            Token::Colon,
+            Token::Identifier,
+            Token::Identifier,
+            Token::EOF,
+        ];
+        let mut lexer = LexerConfig::default()
+            .block_colons(true)
+            .open("input_examples/block_colon.plr")
+            .unwrap();
+        for expected_tok in expected {
+            assert_eq!(lexer.get_token().unwrap(), expected_tok);
+        }
+
+        let mut lexer = Lexer::new("input_examples/block_colon.plr").unwrap();
+        for expected_tok in expected {
+            let expected_tok = match expected_tok {
+                Token::BlockColon => Token::Colon,
+                tok => tok,
+            };
+            assert_eq!(lexer.get_token().unwrap(), expected_tok);
+        }
+    }
+
+    #[test]
+    fn tab_columns() {
+        let expected = [
+            (Token::Identifier, "indented", 4, 8),
+            (Token::Identifier, "mixed", 4, 8),
+            (Token::StringLiteral, "\"\t\"", 12, 16),
+            (Token::Identifier, "after", 18, 26),
+            (Token::Identifier, "x", 0, 0),
+            (Token::EOF, "", 1, 1),
+        ];
+        for tab_width in [4, 8] {
+            let mut lexer = LexerConfig::default()
+                .tab_width(tab_width)
+                .open("input_examples/tabs.plr")
+                .unwrap();
+            for (expected_tok, expected_str, column_4, column_8) in expected {
+                let tok = lexer.get_token().unwrap();
+                let expected_column = if tab_width == 4 { column_4 } else { column_8 };
+                assert_eq!(
+                    (tok, lexer.token_str(), lexer.token_start_column()),
+                    (expected_tok, expected_str, expected_column)
+                );
+            }
+        }
+
+        let mut lexer = Lexer::from_source("tabs.plr", "\t\t@");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter { column: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn skip_to_line() {
+        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        lexer.skip_to_line(3).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "Happén");
+        assert_eq!(lexer.token_start_column(), 8);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "_1with");
+
+        // Going back works as well
+        lexer.skip_to_line(1).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "ファントム");
+
+        assert!(matches!(
+            lexer.skip_to_line(0),
+            Err(LexicError::LineOutOfRange { line: 0, .. })
+        ));
+        assert!(matches!(
+            lexer.skip_to_line(100),
+            Err(LexicError::LineOutOfRange { line: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn config_builder() {
+        let mut lexer = LexerConfig::default()
+            .comment_style(CommentStyle::DoubleDash)
+            .preserve_trivia(true)
+            .block_colons(true)
+            .tab_width(2)
+            .build("config.plr", "\tname: value -- note\nblock:\n");
+        assert!(lexer.config().preserve_trivia);
+        assert_eq!(lexer.config().comment_style, CommentStyle::DoubleDash);
+        let expected = [
+            (Token::Identifier, 2),
+            (Token::Colon, 6),
+            (Token::Identifier, 8),
+            (Token::Comment, 14),
+            (Token::Identifier, 0),
+            (Token::BlockColon, 5),
+            (Token::EOF, 0),
+        ];
+        for (expected_tok, expected_column) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!(
+                (tok, lexer.token_start_column()),
+                (expected_tok, expected_column)
+            );
+        }
+    }
+
+    #[test]
+    fn numbers_and_percent() {
+        let mut lexer = Lexer::new("input_examples/percent.plr").unwrap();
+        let expected = [
+            (Token::PercentLiteral, "50%", "50"),
+            (Token::PercentLiteral, "12.5%", "12.5"),
+            (Token::Identifier, "a", "a"),
+            (Token::Percent, "%", ""),
+            (Token::Identifier, "b", "b"),
+            (Token::IntegerLiteral, "10", "10"),
+            (Token::Percent, "%", ""),
+            (Token::Identifier, "x", "x"),
+            (Token::IntegerLiteral, "7", "7"),
+            (Token::Percent, "%", ""),
+            (Token::IntegerLiteral, "3", "3"),
+            (Token::PercentLiteral, "5%", "5"),
+            (Token::ParensClose, ")", ")"),
+            (Token::FloatLiteral, "0.25", "0.25"),
+            (Token::IntegerLiteral, "100", "100"),
+            (Token::EOF, "", ""),
+        ];
+        for (expected_tok, expected_str, expected_numeric) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!((tok, lexer.token_str()), (expected_tok, expected_str));
+            assert_eq!(lexer.numeric_str(), expected_numeric);
+        }
+
+        let mut lexer = Lexer::from_source("numbers.plr", "12ab@");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter { c: '@', .. })
+        ));
+    }
+
+    #[test]
+    fn from_bytes() {
+        let mut lexer = Lexer::from_bytes("bytes.plr", "páçṕin (x)".as_bytes()).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "páçṕin");
+
+        let error = Lexer::from_bytes("bytes.plr", b"ok\n\tand \xff bad")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            LexicError::InvalidUtf8 {
+                line: 2,
+                column: 9,
+                offset: 8,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn token_starts_line() {
+        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        let mut starts = vec![];
+        loop {
+            let tok = lexer.get_token().unwrap();
+            starts.push((lexer.token_str().to_owned(), lexer.token_starts_line()));
+            if tok == Token::EOF {
+                break;
+            }
+        }
+        let first_of_line: Vec<_> = starts
+            .iter()
+            .filter(|(_, starts_line)| *starts_line)
+            .map(|(lexeme, _)| lexeme.as_str())
+            .collect();
+        assert_eq!(
+            first_of_line,
+            [
+                "ファントム",
+                "parens",
+                "Happén",
+                "_1with",
+                "us_and_ANd",
+                "and",
+                "of",
+                "nanana",
+                "a",
+                "b",
+                "c",
+                "_",
+                "_1234"
+            ]
+        );
+        assert_eq!(starts[1], ("páçṕin".to_owned(), false));
+        assert_eq!(starts.last().unwrap(), &("".to_owned(), false));
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let mut lexer = LexerConfig::default()
+            .string_interpolation(true)
+            .open("input_examples/interpolation.plr")
+            .unwrap();
+        let expected = [
+            (Token::StringStart, "\"hello ", Some("hello ")),
+            (Token::InterpolationStart, "${", None),
+            (Token::Identifier, "name", None),
+            (Token::InterpolationEnd, "}", None),
+            (Token::StringEnd, "!\"", Some("!")),
+            (Token::StringStart, "\"", Some("")),
+            (Token::InterpolationStart, "${", None),
+            (Token::Identifier, "a", None),
+            (Token::InterpolationEnd, "}", None),
+            (Token::InterpolationStart, "${", None),
+            (Token::Identifier, "b", None),
+            (Token::InterpolationEnd, "}", None),
+            (
+                Token::StringFragment,
+                " and \\${not} ",
+                Some(" and ${not} "),
+            ),
+            (Token::InterpolationStart, "${", None),
+            (Token::Identifier, "call", None),
+            (Token::ParensOpen, "(", None),
+            (Token::StringStart, "\"nested ", Some("nested ")),
+            (Token::InterpolationStart, "${", None),
+            (Token::IntegerLiteral, "1", None),
+            (Token::InterpolationEnd, "}", None),
+            (Token::StringEnd, "\"", Some("")),
+            (Token::ParensClose, ")", None),
+            (Token::InterpolationEnd, "}", None),
+            (Token::StringEnd, "\"", Some("")),
+            (Token::StringLiteral, "\"plain $ {x}\"", Some("plain $ {x}")),
+            (Token::EOF, "", None),
+        ];
+        for (expected_tok, expected_str, expected_value) in expected {
+            let tok = lexer.get_token().unwrap();
+            assert_eq!((tok, lexer.token_str()), (expected_tok, expected_str));
+            assert_eq!(lexer.unescaped_string_value().as_deref(), expected_value);
+        }
+
+        // Without interpolation strings are taken as they are
+        let mut lexer = Lexer::new("input_examples/interpolation.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::StringLiteral);
+        assert_eq!(lexer.token_str(), "\"hello ${name}!\"");
+
+        let mut lexer = LexerConfig::default()
+            .string_interpolation(true)
+            .build("interpolation.plr", "x \"open ${x");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.get_token().unwrap(), Token::StringStart);
+        assert_eq!(lexer.get_token().unwrap(), Token::InterpolationStart);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnterminatedString {
+                line: 1,
+                column: 3,
+                offset: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn peek_nth() {
+        let mut lexer = Lexer::from_source("peek.plr", "a (b) c\n  d @");
+        assert_eq!(lexer.peek_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.peek_nth(2).unwrap(), Token::Identifier);
+        assert_eq!(lexer.peek_nth(1).unwrap(), Token::ParensOpen);
+        // Peeking does not change the current token
+        assert_eq!(lexer.token_str(), "");
+
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "a");
+        assert_eq!(lexer.peek_nth(0).unwrap(), Token::ParensOpen);
+        assert!(matches!(
+            lexer.peek_nth(10),
+            Err(LexicError::UnexpectedCharacter { c: '@', .. })
+        ));
+        assert_eq!(lexer.token_str(), "a");
+
+        let mut tokens = Vec::new();
+        while let Ok(tok) = lexer.get_token() {
+            tokens.push((
+                tok,
+                lexer.token_str().to_owned(),
+                lexer.token_start_column(),
+                lexer.token_starts_line(),
+            ));
+        }
+        assert_eq!(
+            tokens,
+            [
+                (Token::ParensOpen, "(".to_owned(), 2, false),
+                (Token::Identifier, "b".to_owned(), 3, false),
+                (Token::ParensClose, ")".to_owned(), 4, false),
+                (Token::Identifier, "c".to_owned(), 6, false),
+                (Token::Identifier, "d".to_owned(), 2, true),
+            ]
+        );
+
+        let mut lexer = Lexer::from_source("peek.plr", "x");
+        assert_eq!(lexer.peek_nth(3).unwrap(), Token::EOF);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+    }
+
+    #[test]
+    fn non_ascii_digit_in_identifier() {
+        let mut lexer = Lexer::new("input_examples/non_ascii_digit.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "ok1");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter {
+                c: '٣',
+                line: 2,
+                column: 6,
+                ..
+            })
+        ));
+
+        // Letters of any script are still fine
+        let mut lexer = Lexer::from_source("ident.plr", "参1 ń_2");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "参1");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "ń_2");
+    }
+
+    #[test]
+    fn expect_eof() {
+        let mut lexer = Lexer::from_source("expect.plr", "name: value\n");
+        lexer.expect(Token::Identifier).unwrap();
+        lexer.expect(Token::Colon).unwrap();
+        lexer.expect(Token::Identifier).unwrap();
+        lexer.expect_eof().unwrap();
+
+        let mut lexer = Lexer::from_source("expect.plr", "name\n  trailing (");
+        lexer.expect(Token::Identifier).unwrap();
+        let error = lexer.expect_eof().unwrap_err();
+        assert!(matches!(
+            error,
+            LexicError::UnexpectedToken {
+                expected: Token::EOF,
+                found: Token::Identifier,
+                line: 2,
+                column: 3,
+                offset: 7,
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "expect.plr:2:3: Expected EOF, found Identifier"
+        );
+        // Lexing errors are passed along
+        let mut lexer = Lexer::from_source("expect.plr", "@");
+        assert!(matches!(
+            lexer.expect_eof(),
+            Err(LexicError::UnexpectedCharacter { .. })
+        ));
+    }
+
+    #[test]
+    fn byte_to_positions() {
+        let code = "a b\n\tcé\n\nlast";
+        let lexer = Lexer::from_source("positions.plr", code);
+        // Unsorted and repeated, with line starts, the middle of `é`, the
+        // end of the source and past it
+        let offsets = [14, 0, 7, 4, 3, 9, 5, 16, 100, 7, 10];
+        assert_eq!(code.len(), 14);
+        let expected: Vec<_> = offsets.iter().map(|&o| lexer.line_col_at(o)).collect();
+        assert_eq!(lexer.byte_to_positions(&offsets), expected);
+        assert_eq!(
+            expected,
+            [
+                (4, 5),
+                (1, 1),
+                (2, 7),
+                (2, 1),
+                (1, 4),
+                (3, 1),
+                (2, 5),
+                (4, 5),
+                (4, 5),
+                (2, 7),
+                (4, 1)
+            ]
+        );
+        assert!(lexer.byte_to_positions(&[]).is_empty());
+
+        // From where a snippet starts in its document
+        let lexer = Lexer::with_initial_position("doc.md", code, 10, 5);
+        let expected: Vec<_> = offsets.iter().map(|&o| lexer.line_col_at(o)).collect();
+        assert_eq!(lexer.byte_to_positions(&offsets), expected);
+    }
+
+    #[test]
+    fn relex_line() {
+        let mut lexer = Lexer::from_source("relex.plr", "a b\nc d\n\tlast");
+        assert_eq!(lexer.line_col_at(9), (3, 5));
+
+        let tokens = lexer.relex_line(2, "longer_name (x)").unwrap();
+        let tokens: Vec<_> = tokens
+            .into_iter()
+            .map(|(tok, span)| (tok, &lexer.code[span.range()]))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (Token::Identifier, "longer_name"),
+                (Token::ParensOpen, "("),
+                (Token::Identifier, "x"),
+                (Token::ParensClose, ")"),
+            ]
+        );
+        assert_eq!(&*lexer.code, "a b\nlonger_name (x)\n\tlast");
+        // The lexer goes on from the next line
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "last");
+
+        // The offsets reflect the edit
+        let last = lexer.code.find("last").unwrap();
+        assert_eq!(lexer.line_col_at(last), (3, 5));
+        assert_eq!(lexer.line_col_at(last - 2), (2, 16));
+        lexer.skip_to_line(3).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_span(), Span::from(last..last + 4));
+
+        assert!(matches!(
+            lexer.relex_line(4, "x"),
+            Err(LexicError::LineOutOfRange { line_count: 3, .. })
+        ));
+
+        // A broken line after the edited one is not its error
+        let mut lexer = Lexer::from_source("relex.plr", "a\n@");
+        let tokens = lexer.relex_line(1, "x").unwrap();
+        assert_eq!(tokens, [(Token::Identifier, Span::from(0..1))]);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter {
+                c: '@',
+                line: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            lexer.relex_line(1, "x @"),
+            Err(LexicError::UnexpectedCharacter {
+                c: '@',
+                line: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn colon_colon() {
+        let mut lexer = Lexer::new("input_examples/colon_colon.plr").unwrap();
+        let mut tokens = Vec::new();
+        loop {
+            match lexer.get_token().unwrap() {
+                Token::EOF => break,
+                tok => tokens.push((tok, lexer.token_str().to_owned())),
+            }
+        }
+        assert_eq!(
+            tokens,
+            [
+                (Token::Identifier, "a".to_owned()),
+                (Token::ColonColon, "::".to_owned()),
+                (Token::Identifier, "b".to_owned()),
+                (Token::Colon, ":".to_owned()),
+                (Token::Identifier, "c".to_owned()),
+                // Maximal munch, the first two make a path separator
+                (Token::ColonColon, "::".to_owned()),
+                (Token::Colon, ":".to_owned()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn from_mmap() {
+        let path = "input_examples/simple1.plr";
+        let mut mapped = Lexer::from_mmap(path).unwrap();
+        let mut read = Lexer::new(path).unwrap();
+        loop {
+            let tok = mapped.get_token().unwrap();
+            assert_eq!(tok, read.get_token().unwrap());
+            assert_eq!(mapped.token_str(), read.token_str());
+            assert_eq!(mapped.token_span(), read.token_span());
+            if tok == Token::EOF {
+                break;
+            }
+        }
+
+        // Editing copies the map out instead of writing to the file
+        let mut mapped = Lexer::from_mmap(path).unwrap();
+        mapped.relex_line(12, "edited").unwrap();
+        assert!(mapped.code.contains("\nedited\n"));
+        assert!(!std::fs::read_to_string(path).unwrap().contains("edited"));
+
+        let result = Lexer::from_mmap("input_examples/invalid_utf8.plr");
+        assert!(matches!(
+            result,
+            Err(LexicError::InvalidUtf8 { line: 2, .. })
+        ));
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn from_gzip() {
+        let mut compressed = Lexer::from_gzip("input_examples/simple1.plr.gz").unwrap();
+        let mut plain = Lexer::new("input_examples/simple1.plr").unwrap();
+        assert_eq!(compressed.input_filepath(), plain.input_filepath());
+        loop {
+            let tok = compressed.get_token().unwrap();
+            assert_eq!(tok, plain.get_token().unwrap());
+            assert_eq!(compressed.token_str(), plain.token_str());
+            assert_eq!(compressed.token_span(), plain.token_span());
+            if tok == Token::EOF {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn calculator_preset() {
+        let mut lexer = LexerConfig::calculator().build("calc.plr", "(1 + 2) * 3.5");
+        let mut tokens = Vec::new();
+        loop {
+            match lexer.get_token().unwrap() {
+                Token::EOF => break,
+                tok => tokens.push((tok, lexer.token_str().to_owned())),
+            }
+        }
+        let expected = [
+            (Token::ParensOpen, "("),
+            (Token::IntegerLiteral, "1"),
+            (Token::Plus, "+"),
+            (Token::IntegerLiteral, "2"),
+            (Token::ParensClose, ")"),
+            (Token::Star, "*"),
+            (Token::FloatLiteral, "3.5"),
+        ];
+        let expected: Vec<_> = expected.iter().map(|&(t, s)| (t, s.to_owned())).collect();
+        assert_eq!(tokens, expected);
+
+        // No keywords, and the operators break numbers and identifiers
+        let mut lexer = LexerConfig::calculator().build("calc.plr", "if-1/x");
+        let tokens = lexer.tokenize_all().tokens;
+        assert_eq!(
+            tokens,
+            [
+                Token::Identifier,
+                Token::Minus,
+                Token::IntegerLiteral,
+                Token::Slash,
+                Token::Identifier
+            ]
+        );
+
+        // Not operators by default
+        let mut lexer = Lexer::from_source("calc.plr", "1 + 2");
+        assert_eq!(lexer.get_token().unwrap(), Token::IntegerLiteral);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter { c: '+', .. })
+        ));
+    }
+
+    #[test]
+    fn keywords() {
+        for keyword in ["fn", "let", "if", "return"] {
+            assert!(is_keyword(keyword), "{}", keyword);
+        }
+        for not_keyword in ["", "Let", "lets", "iff", "_", "and"] {
+            assert!(!is_keyword(not_keyword), "{}", not_keyword);
+        }
+
+        // Same as the lexer
+        let mut lexer = Lexer::from_source("keywords.plr", "let x if letter");
+        let mut tokens = Vec::new();
+        while let Ok(tok @ (Token::Identifier | Token::Keyword)) = lexer.get_token() {
+            assert_eq!(tok == Token::Keyword, is_keyword(lexer.token_str()));
+            tokens.push(tok);
+        }
+        assert_eq!(
+            tokens,
+            [
+                Token::Keyword,
+                Token::Identifier,
+                Token::Keyword,
+                Token::Identifier
+            ]
+        );
+    }
+
+    #[test]
+    fn question() {
+        let mut lexer = Lexer::new("input_examples/question.plr").unwrap();
+        let mut tokens = Vec::new();
+        loop {
+            match lexer.get_token().unwrap() {
+                Token::EOF => break,
+                tok => tokens.push((tok, lexer.token_str().to_owned())),
+            }
+        }
+        assert_eq!(
+            tokens,
+            [
+                (Token::Identifier, "x".to_owned()),
+                (Token::Question, "?".to_owned()),
+                (Token::Identifier, "call".to_owned()),
+                (Token::ParensOpen, "(".to_owned()),
+                (Token::ParensClose, ")".to_owned()),
+                // There is no `??` operator (yet), so these are two tokens
+                (Token::Question, "?".to_owned()),
+                (Token::Question, "?".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn with_initial_position() {
+        let code = "a @\n  b\n\t@";
+        let mut lexer = Lexer::with_initial_position("doc.md", code, 10, 5);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_start_column(), 4);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter {
+                line: 10,
+                column: 7,
+                offset: 2,
+                ..
+            })
+        ));
+        lexer.skip_to_line(11).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.line_col_at(lexer.token_span().start), (11, 3));
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter {
+                line: 12,
+                column: 5,
+                ..
+            })
+        ));
+        assert_eq!(lexer.line_col_at(0), (10, 5));
+        lexer.recover();
+        assert_eq!(lexer.expect(Token::Colon).unwrap_err().position(), (12, 6));
+
+        // The first line is not there to skip to
+        assert!(lexer.skip_to_line(1).is_err());
+        lexer.skip_to_line(10).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_start_column(), 4);
+    }
+
+    #[test]
+    fn numeric_overflow() {
+        let mut lexer = Lexer::new("input_examples/numeric_overflow.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::IntegerLiteral);
+        assert_eq!(lexer.numeric_value::<u64>().unwrap(), u64::MAX);
+        assert!(lexer.numeric_value::<i64>().is_err());
+        assert_eq!(lexer.get_token().unwrap(), Token::PercentLiteral);
+        assert_eq!(lexer.numeric_value::<u8>().unwrap(), 255);
+
+        assert_eq!(lexer.get_token().unwrap(), Token::IntegerLiteral);
+        let error = lexer.numeric_value::<u64>().unwrap_err();
+        assert!(matches!(
+            &error,
+            LexicError::NumericOverflow {
+                line: 2,
+                column: 3,
+                lexeme,
+                ..
+            } if lexeme == "18446744073709551616"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "input_examples/numeric_overflow.plr:2:3: \
+             Number 18446744073709551616 is too large"
+        );
+    }
+
+    #[test]
+    fn line_continuation() {
+        let mut lexer = Lexer::new("input_examples/line_continuation.plr").unwrap();
+        let mut tokens = Vec::new();
+        loop {
+            match lexer.get_token() {
+                Ok(Token::EOF) => break,
+                Ok(tok) => tokens.push((
+                    tok,
+                    lexer.line_col_at(lexer.token_span().start),
+                    lexer.token_starts_line(),
+                )),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert_eq!(
+            tokens,
+            [
+                (Token::Identifier, (1, 1), true),
+                (Token::ParensOpen, (1, 7), false),
+                (Token::Identifier, (1, 8), false),
+                // Still on the logical line of `total`
+                (Token::Identifier, (2, 5), false),
+                (Token::ParensClose, (2, 6), false),
+                (Token::Identifier, (3, 1), true),
+            ]
+        );
+
+        let mut lexer = LexerConfig::default()
+            .preserve_trivia(true)
+            .open("input_examples/line_continuation.plr")
+            .unwrap();
+        let tokens = lexer.tokenize_all();
+        assert_eq!(tokens.tokens[3], Token::LineContinuation);
+        assert_eq!(
+            &lexer.code[tokens.ranges[3].clone()],
+            "\\\n",
+            "The continuation spans the `\\` and the line break"
+        );
+        assert_eq!(tokens.ranges[3], 9..11);
+        assert_eq!(tokens.tokens.len(), 7);
+
+        let mut lexer = Lexer::from_source("continuation.plr", "a \\ b");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter {
+                c: '\\',
+                column: 3,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing::{field, span, Event, Metadata, Subscriber};
+
+        /// Records the name and `file` field of every span
+        #[derive(Default)]
+        struct SpanRecorder(Arc<Mutex<Vec<(String, String)>>>);
+
+        struct FileVisitor(String);
+
+        impl field::Visit for FileVisitor {
+            fn record_debug(&mut self, field: &field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "file" {
+                    self.0 = format!("{:?}", value);
+                }
+            }
+        }
+
+        impl Subscriber for SpanRecorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                let mut visitor = FileVisitor(String::new());
+                span.record(&mut visitor);
+                let mut spans = self.0.lock().unwrap();
+                spans.push((span.metadata().name().to_owned(), visitor.0));
+                span::Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let spans = Arc::default();
+        let recorder = SpanRecorder(Arc::clone(&spans));
+        tracing::subscriber::with_default(recorder, || {
+            let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+            while lexer.get_token().unwrap() != Token::EOF {}
+        });
+        let spans = spans.lock().unwrap();
+        assert_eq!(
+            *spans,
+            [
+                ("_open".to_owned(), "input_examples/simple1.plr".to_owned()),
+                ("lexer".to_owned(), "input_examples/simple1.plr".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn colon_equals() {
+        let mut lexer = Lexer::new("input_examples/colon_equals.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        let error = lexer.get_token().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "input_examples/colon_equals.plr:1:3: Unexpected ':=', did you mean '='?"
+        );
+
+        let mut lexer = Lexer::from_source("equals.plr", "x=1");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.get_token().unwrap(), Token::Equals);
+        assert_eq!(lexer.get_token().unwrap(), Token::IntegerLiteral);
+    }
+
+    #[test]
+    fn lexeme_bytes() {
+        let mut lexer = Lexer::from_source("bytes.plr", "ファントム páçṕin");
+        while lexer.get_token().unwrap() != Token::EOF {
+            assert_eq!(lexer.lexeme_bytes(), lexer.token_str().as_bytes());
+            assert!(lexer.lexeme_bytes().len() > lexer.token_str().chars().count());
+        }
+        assert_eq!(lexer.lexeme_bytes(), b"");
+    }
+
+    #[test]
+    fn validate_config() {
+        let config = LexerConfig::default()
+            .max_errors(1)
+            .string_interpolation(true);
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(LexerConfig::default().validate(), Ok(()));
+
+        let config = LexerConfig::default().preserve_trivia(true).max_errors(0);
+        assert_eq!(config.validate(), Err(ConfigError::ZeroMaxErrors));
+        let result = std::panic::catch_unwind(|| config.build("config.plr", ""));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn inline_code() {
+        let mut lexer = LexerConfig::default()
+            .inline_code(true)
+            .open("input_examples/inline_code.plr")
+            .unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.inline_code_text(), None);
+        assert_eq!(lexer.get_token().unwrap(), Token::InlineCode);
+        assert_eq!(lexer.token_str(), "`code`");
+        assert_eq!(lexer.inline_code_text(), Some("code"));
+        assert_eq!(lexer.get_token().unwrap(), Token::InlineCode);
+        assert_eq!(lexer.inline_code_text(), Some("x \\n \"y\""));
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnterminatedInlineCode {
+                line: 2,
+                column: 1,
+                ..
+            })
+        ));
+
+        // Not inline code by default
+        let mut lexer = Lexer::new("input_examples/inline_code.plr").unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter { c: '`', .. })
+        ));
+    }
+
+    #[test]
+    fn count_lines() {
+        // No trailing newline
+        let lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        assert_eq!(lexer.count_lines(), 16);
+        let lexer = Lexer::new("input_examples/broken1.plr").unwrap();
+        assert_eq!(lexer.count_lines(), 2);
+
+        for (code, lines) in [("", 0), ("\n", 1), ("a", 1), ("a\nb", 2), ("a\nb\n\n", 3)] {
+            assert_eq!(
+                Lexer::from_source("lines.plr", code).count_lines(),
+                lines,
+                "{:?}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn form_feed() {
+        let mut lexer = Lexer::new("input_examples/form_feed.plr").unwrap();
+        let mut tokens = Vec::new();
+        while let Ok(tok @ (Token::Identifier | Token::Colon)) = lexer.get_token() {
+            tokens.push((
+                lexer.token_str().to_owned(),
+                lexer.line_col_at(lexer.token_span().start),
+                lexer.token_starts_line(),
+            ));
+            assert_eq!(tok == Token::Colon, lexer.token_str() == ":");
+        }
+        assert_eq!(
+            tokens,
+            [
+                ("page".to_owned(), (1, 1), true),
+                ("one".to_owned(), (1, 6), false),
+                (":".to_owned(), (1, 10), false),
+                ("page".to_owned(), (3, 2), true),
+                ("two".to_owned(), (3, 9), false),
+            ]
+        );
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+    }
+
+    #[test]
+    fn io_errors() {
+        let result = Lexer::new("input_examples/missing.plr");
+        let error = match result {
+            Err(error @ LexicError::Io { .. }) => error,
+            _ => panic!("Expected an io error"),
+        };
+        assert!(matches!(
+            &error,
+            LexicError::Io { error, .. } if error.kind() == io::ErrorKind::NotFound
+        ));
+        assert!(error.source().is_some());
+        assert!(error
+            .to_string()
+            .starts_with("input_examples/missing.plr: Could not read the file: "));
+
+        // Reading a file checks the UTF-8 the same as from_bytes
+        assert!(matches!(
+            Lexer::new("input_examples/invalid_utf8.plr"),
+            Err(LexicError::InvalidUtf8 {
+                line: 2,
+                column: 5,
+                offset: 10,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn conditional_directives() {
+        fn identifiers(config: LexerConfig) -> Vec<String> {
+            let mut lexer = config.open("input_examples/conditional.plr").unwrap();
+            let mut identifiers = Vec::new();
+            while lexer.get_token().unwrap() != Token::EOF {
+                identifiers.push(lexer.token_str().to_owned());
+            }
+            identifiers
+        }
+
+        let config = LexerConfig::default().directives(true);
+        assert_eq!(identifiers(config.clone()), ["always", "after"]);
+        assert_eq!(
+            identifiers(config.clone().define("DEBUG")),
+            ["always", "debug_only", "after"]
+        );
+        assert_eq!(
+            identifiers(config.clone().define("DEBUG").define("VERBOSE")),
+            ["always", "debug_only", "verbose_only", "after"]
+        );
+        // Nested in a disabled region, so still skipped
+        assert_eq!(
+            identifiers(config.clone().define("VERBOSE")),
+            ["always", "after"]
+        );
+        // Without directives they are plain comments
+        assert_eq!(
+            identifiers(LexerConfig::default()),
+            ["always", "debug_only", "verbose_only", "after"]
+        );
+
+        let mut lexer = config.clone().build("directives.plr", "a\n#endif\n");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        let error = lexer.get_token().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "directives.plr:2:1: #endif without a matching #if"
+        );
+
+        let mut lexer = config.clone().build("directives.plr", "  #if A\nb\n");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnterminatedIf {
+                line: 1,
+                column: 3,
+                offset: 2,
+                ..
+            })
+        ));
+        let mut lexer = config.define("A").build("directives.plr", "#if A\nb\n");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnterminatedIf { line: 1, .. })
+        ));
+
+        let mut lexer = LexerConfig::default()
+            .directives(true)
+            .build("directives.plr", "#if A B\n#endif\n");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::InvalidDirective { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn dots() {
+        let mut lexer = Lexer::new("input_examples/dots.plr").unwrap();
+        assert_tokens(
+            &mut lexer,
+            &[
+                (Token::Identifier, "args"),
+                (Token::Ellipsis, "..."),
+                (Token::IntegerLiteral, "0"),
+                (Token::DotDot, ".."),
+                (Token::IntegerLiteral, "10"),
+                (Token::Identifier, "point"),
+                (Token::Dot, "."),
+                (Token::Identifier, "x"),
+                (Token::FloatLiteral, "1.5"),
+                (Token::Ellipsis, "..."),
+                (Token::Dot, "."),
+            ],
+        );
+    }
+
+    #[test]
+    fn last_token() {
+        let mut lexer = Lexer::from_source("last.plr", "a = (1 @");
+        assert_eq!(lexer.last_token(), None);
+        for expected in [
+            Token::Identifier,
+            Token::Equals,
+            Token::ParensOpen,
+            Token::IntegerLiteral,
+        ] {
+            assert_eq!(lexer.get_token().unwrap(), expected);
+            assert_eq!(lexer.last_token(), Some(expected));
+        }
+        assert!(lexer.get_token().is_err());
+        assert_eq!(lexer.last_token(), Some(Token::IntegerLiteral));
+        lexer.recover();
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+        assert_eq!(lexer.last_token(), Some(Token::IntegerLiteral));
+    }
+
+    #[test]
+    fn pipe_and_ampersand() {
+        let mut lexer = Lexer::new("input_examples/pipe.plr").unwrap();
+        assert_tokens(
+            &mut lexer,
+            &[
+                (Token::Identifier, "a"),
+                (Token::Pipe, "|"),
+                (Token::Identifier, "b"),
+                (Token::Ampersand, "&"),
+                (Token::Identifier, "c"),
+                (Token::Identifier, "x"),
+                (Token::Pipe, "|"),
+                (Token::Pipe, "|"),
+                (Token::Identifier, "y"),
+                (Token::Ampersand, "&"),
+                (Token::Identifier, "z"),
+            ],
+        );
+    }
+
+    #[test]
+    fn mixed_newlines() {
+        let mut lexer = LexerConfig::default()
+            .block_colons(true)
+            .open("input_examples/mixed_newlines.plr")
+            .unwrap();
+        let tokenized = lexer.tokenize_all();
+        let lines: Vec<_> = tokenized.errors.iter().map(|e| e.position().0).collect();
+        assert_eq!(lines, [5, 8]);
+        assert!(matches!(
+            tokenized.errors[1],
+            LexicError::UnexpectedCharacter {
+                c: '@',
+                column: 5,
+                ..
+            }
+        ));
+        assert_eq!(
+            tokenized.tokens,
+            [
+                Token::Identifier,
+                Token::Identifier,
+                Token::Identifier,
+                Token::BlockColon,
+                Token::Identifier,
+                Token::Identifier,
+                Token::Identifier,
+                Token::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn is_at_eof() {
+        let mut lexer = Lexer::from_source("eof.plr", "a (b)");
+        for _ in 0..3 {
+            assert!(!lexer.is_at_eof());
+            lexer.get_token().unwrap();
+        }
+        // The last token is peeked, it still has to be returned
+        assert_eq!(lexer.peek_token().unwrap(), Token::ParensClose);
+        assert!(!lexer.is_at_eof());
+        assert_eq!(lexer.get_token().unwrap(), Token::ParensClose);
+        assert!(lexer.is_at_eof());
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+        assert!(lexer.is_at_eof());
+    }
+
+    #[test]
+    fn numeric_suffixes() {
+        let mut lexer = Lexer::new("input_examples/number_suffixes.plr").unwrap();
+        let mut suffixes = Vec::new();
+        loop {
+            let token = lexer.get_token().unwrap();
+            if token == Token::EOF {
+                break;
+            }
+            let suffix = lexer.numeric_suffix().map(|span| &lexer.code[span.range()]);
+            suffixes.push((
+                token,
+                lexer.numeric_str().to_owned(),
+                suffix.map(str::to_owned),
+            ));
+        }
+        let suffix = |s: &str| Some(s.to_owned());
+        assert_eq!(
+            suffixes,
+            [
+                (Token::IntegerLiteral, "42".to_owned(), suffix("i32")),
+                (Token::FloatLiteral, "3.0".to_owned(), suffix("f64")),
+                (Token::IntegerLiteral, "7".to_owned(), None),
+                (Token::IntegerLiteral, "1".to_owned(), suffix("_u8")),
+                (Token::ParensOpen, "(".to_owned(), None),
+                (Token::IntegerLiteral, "5".to_owned(), suffix("wat")),
+                (Token::ParensClose, ")".to_owned(), None),
+            ]
+        );
+
+        let mut lexer = Lexer::from_source("suffixes.plr", "42i32 50%");
+        lexer.get_token().unwrap();
+        assert_eq!(lexer.numeric_value::<u8>().unwrap(), 42);
+        lexer.get_token().unwrap();
+        assert_eq!(lexer.numeric_suffix(), None);
+        assert_eq!(lexer.numeric_value::<u8>().unwrap(), 50);
+    }
+
+    #[test]
+    fn clone_at() {
+        fn next(lexer: &mut Lexer) -> (Token, Span, usize, bool) {
+            let token = lexer.get_token().unwrap();
+            let span = lexer.token_span();
+            (
+                token,
+                span,
+                lexer.token_start_column(),
+                lexer.token_starts_line(),
+            )
+        }
+
+        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        let split = lexer.line_offsets()[7];
+        let mut second_half = lexer.clone_at(split).unwrap();
+
+        let mut whole = Vec::new();
+        loop {
+            whole.push(next(&mut lexer));
+            if whole.last().unwrap().0 == Token::EOF {
+                break;
+            }
+        }
+        let mut first_half = lexer.clone_at(0).unwrap();
+        let mut halves = Vec::new();
+        loop {
+            let token = next(&mut first_half);
+            if token.1.start >= split {
+                break;
+            }
+            halves.push(token);
+        }
+        loop {
+            halves.push(next(&mut second_half));
+            if halves.last().unwrap().0 == Token::EOF {
+                break;
+            }
+        }
+        assert_eq!(halves, whole);
+
+        assert!(matches!(
+            lexer.clone_at(1),
+            Err(LexicError::InvalidOffset { offset: 1, .. })
+        ));
+        assert!(lexer.clone_at(lexer.code.len() + 1).is_err());
+    }
+
+    #[test]
+    fn normalized_lexeme() {
+        let mut lexer = LexerConfig::default()
+            .case_insensitive_identifiers(true)
+            .build("case.plr", "Foo foo \"Foo\"");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        let (first_raw, first) = (
+            lexer.token_str().to_owned(),
+            lexer.normalized_lexeme().into_owned(),
+        );
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_ne!(first_raw, lexer.token_str());
+        assert_eq!(first, lexer.normalized_lexeme());
+        assert_eq!(first, "foo");
+        // Only identifiers are normalized
+        assert_eq!(lexer.get_token().unwrap(), Token::StringLiteral);
+        assert_eq!(lexer.normalized_lexeme(), "\"Foo\"");
+
+        // The lexeme as it is when disabled, the default
+        let mut lexer = Lexer::from_source("case.plr", "Foo");
+        lexer.get_token().unwrap();
+        assert_eq!(lexer.normalized_lexeme(), "Foo");
+    }
+
+    #[test]
+    fn case_folded_keyword_warning() {
+        let code = "Let x = 1\nlet y = x";
+        let mut lexer = LexerConfig::default()
+            .case_insensitive_keywords(true)
+            .build("case.plr", code);
+        let tokens = lexer.tokenize_all().tokens;
+        assert_eq!(tokens[0], Token::Keyword);
+        assert_eq!(tokens[4], Token::Keyword);
+        assert_eq!(
+            lexer.warnings(),
+            [LexicWarning::CaseFoldedKeyword {
+                lexeme: "Let".to_owned(),
+                file: "case.plr".into(),
+                line: 1,
+                column: 1,
+                offset: 0,
+            }]
+        );
+        assert_eq!(
+            lexer.warnings()[0].to_string(),
+            "case.plr:1:1: warning: 'Let' is taken as the keyword 'let'"
+        );
+
+        // Case sensitive by default, so no warning either
+        let mut lexer = Lexer::from_source("case.plr", code);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(lexer.warnings().is_empty());
+    }
+
+    #[test]
+    fn group_delimiters() {
+        let config = LexerConfig::default().group_delimiters(&[('(', ')'), ('[', ']'), ('{', '}')]);
+        let mut lexer = config
+            .clone()
+            .open("input_examples/group_delimiters.plr")
+            .unwrap();
+        let tokenized = lexer.tokenize_all();
+        assert_eq!(
+            tokenized.tokens[..8],
+            [
+                Token::Identifier,
+                Token::ParensOpen,
+                Token::Identifier,
+                Token::ParensOpen,
+                Token::IntegerLiteral,
+                Token::ParensClose,
+                Token::ParensClose,
+                Token::Identifier,
+            ]
+        );
+        let errors: Vec<_> = tokenized.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "input_examples/group_delimiters.plr:2:8: Mismatched ')', expected ']'",
+                "input_examples/group_delimiters.plr:3:11: Mismatched '}', expected ')'",
+            ]
+        );
+
+        // Only parens by default
+        let mut lexer = Lexer::from_source("groups.plr", "[a]");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::UnexpectedCharacter { c: '[', .. })
+        ));
+
+        let mut lexer = config.emit_group_markers(true).build("groups.plr", "{a}");
+        let tokens = lexer.tokenize_all().tokens;
+        assert_eq!(
+            tokens,
+            [
+                Token::ParensOpen,
+                Token::GroupBegin,
+                Token::Identifier,
+                Token::GroupEnd,
+                Token::ParensClose,
+            ]
+        );
+        assert_eq!(
+            LexerConfig::default()
+                .group_delimiters(&[('<', '"')])
+                .validate(),
+            Err(ConfigError::ReservedGroupDelimiter('"'))
+        );
+        // Braces also end interpolations
+        assert_eq!(
+            LexerConfig::default()
+                .group_delimiters(&[('{', '}')])
+                .string_interpolation(true)
+                .validate(),
+            Err(ConfigError::ReservedGroupDelimiter('}'))
+        );
+    }
+
+    #[test]
+    fn progress() {
+        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        assert_eq!(lexer.progress(), 0.0);
+        let mut last_progress = 0.0;
+        while lexer.get_token().unwrap() != Token::EOF {
+            assert!(lexer.progress() >= last_progress);
+            last_progress = lexer.progress();
+        }
+        assert_eq!(lexer.progress(), 1.0);
+        assert_eq!(lexer.bytes_consumed(), lexer.code.len());
+
+        assert_eq!(Lexer::from_source("empty.plr", "").progress(), 1.0);
+    }
+
+    #[test]
+    fn unit_token() {
+        let mut lexer = LexerConfig::default()
+            .unit_token(true)
+            .open("input_examples/unit.plr")
+            .unwrap();
+        assert_tokens(
+            &mut lexer,
+            &[
+                (Token::Identifier, "f"),
+                (Token::Unit, "()"),
+                (Token::Identifier, "g"),
+                (Token::Unit, "(  )"),
+                (Token::Identifier, "h"),
+                (Token::ParensOpen, "("),
+                (Token::Identifier, "x"),
+                (Token::ParensClose, ")"),
+            ],
+        );
+
+        let mut lexer = Lexer::new("input_examples/unit.plr").unwrap();
+        let tokens = lexer.tokenize_all().tokens;
+        assert_eq!(
+            tokens[..3],
+            [Token::Identifier, Token::ParensOpen, Token::ParensClose]
+        );
+    }
+
+    #[test]
+    fn errors_as_iterator() {
+        let mut lexer = Lexer::from_source("iter.plr", "a @ b\n@ c");
+        let results: Vec<_> = lexer
+            .errors_as_iterator()
+            .map(|result| result.map_err(|e| e.position()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                Ok(Token::Identifier),
+                Err((1, 3)),
+                Ok(Token::Identifier),
+                Err((2, 1)),
+                Ok(Token::Identifier),
+            ]
+        );
+
+        let mut lexer = LexerConfig::default()
+            .max_errors(1)
+            .build("iter.plr", "@ @ a");
+        let results: Vec<_> = lexer.errors_as_iterator().collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(LexicError::TooManyErrors { limit: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn from_reader() {
+        /// Hands out the chunks one at a time, no matter the buffer size
+        struct Chunks(VecDeque<Vec<u8>>);
+
+        impl io::Read for Chunks {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.pop_front() {
+                    Some(chunk) => {
+                        buf[..chunk.len()].copy_from_slice(&chunk);
+                        Ok(chunk.len())
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+
+        // The `é` is split between the two reads
+        let code = "café au lait".as_bytes();
+        let split = code.iter().position(|&b| b == 0xC3).unwrap() + 1;
+        let chunks = Chunks(VecDeque::from([
+            code[..split].to_vec(),
+            code[split..].to_vec(),
+        ]));
+        let mut lexer = Lexer::from_reader("reader.plr", chunks).unwrap();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "café");
+
+        let chunks = Chunks(VecDeque::from([b"ok\nbad ".to_vec(), b"\xFF\xFE".to_vec()]));
+        assert!(matches!(
+            Lexer::from_reader("reader.plr", chunks),
+            Err(LexicError::InvalidUtf8 {
+                line: 2,
+                column: 5,
+                offset: 7,
+                ..
+            })
+        ));
+        // Cut by the end of the input
+        let chunks = Chunks(VecDeque::from([code[..split].to_vec()]));
+        assert!(matches!(
+            Lexer::from_reader("reader.plr", chunks),
+            Err(LexicError::InvalidUtf8 { offset: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn split_documents() {
+        let name = "input_examples/documents.plr";
+        let source = std::fs::read_to_string(name).unwrap();
+        let mut lexers = Lexer::split_documents(name, &source);
+        assert_eq!(lexers.len(), 3);
+        let starts: Vec<_> = lexers.iter().map(|lexer| lexer.line_col_at(0)).collect();
+        assert_eq!(starts, [(1, 1), (4, 1), (8, 1)]);
+
+        let first_tokens: Vec<_> = lexers
+            .iter_mut()
+            .map(|lexer| {
+                lexer.get_token().unwrap();
+                lexer.token_str().to_owned()
+            })
+            .collect();
+        assert_eq!(first_tokens, ["first", "second", "third"]);
+        // The second document has an error, reported in the whole source
+        let errors = lexers[1].tokenize_all().errors;
+        assert_eq!(errors[0].position(), (5, 7));
+
+        let lexers = LexerConfig::default()
+            .document_separator("===")
+            .split_documents("docs.plr", "a\n---\nb");
+        assert_eq!(lexers.len(), 1);
+    }
+
+    #[test]
+    fn display_position_prefix() {
+        let mut lexer = Lexer::from_source("prefix.plr", "a\n  b @");
+        let error = lexer.tokenize_all().errors.remove(0);
+        // What editors parse to jump to the error
+        assert!(error.to_string().starts_with("prefix.plr:2:5: "));
+        assert_eq!(
+            error.to_string(),
+            "prefix.plr:2:5: Unexpected character '@'"
+        );
+    }
+
+    #[test]
+    fn checkpoint_restore() {
+        fn lex_some(lexer: &mut Lexer, count: usize) -> Vec<(Token, Span, usize, bool)> {
+            (0..count)
+                .map(|_| {
+                    let token = lexer.get_token().unwrap();
+                    let span = lexer.token_span();
+                    (
+                        token,
+                        span,
+                        lexer.token_start_column(),
+                        lexer.token_starts_line(),
+                    )
+                })
+                .collect()
+        }
+
+        let mut lexer = Lexer::new("input_examples/simple1.plr").unwrap();
+        lex_some(&mut lexer, 3);
+        lexer.peek_token().unwrap();
+        let checkpoint = lexer.checkpoint();
+        let first_pass = lex_some(&mut lexer, 10);
+        let line_after = lexer.line_col_at(lexer.token_span().start);
+
+        lexer.restore(checkpoint.clone());
+        assert_eq!(lex_some(&mut lexer, 10), first_pass);
+        assert_eq!(lexer.line_col_at(lexer.token_span().start), line_after);
+
+        // The same checkpoint can be restored many times
+        lex_some(&mut lexer, 5);
+        lexer.restore(checkpoint);
+        assert_eq!(lex_some(&mut lexer, 10), first_pass);
+    }
+
+    #[test]
+    fn deeply_nested_parens() {
+        const DEPTH: usize = 100_000;
+        let code = format!("{}x{}", "(".repeat(DEPTH), ")".repeat(DEPTH));
+        for emit_group_markers in [false, true] {
+            let mut lexer = LexerConfig::default()
+                .emit_group_markers(emit_group_markers)
+                .build("nested.plr", code.as_str());
+            let tokenized = lexer.tokenize_all();
+            assert!(tokenized.errors.is_empty());
+            let parens = if emit_group_markers { 4 } else { 2 };
+            assert_eq!(tokenized.tokens.len(), DEPTH * parens + 1);
+        }
+    }
+
+    #[test]
+    fn hash_token() {
+        let mut lexer = LexerConfig::default()
+            .comment_style(CommentStyle::DoubleSlash)
+            .group_delimiters(&[('(', ')'), ('[', ']')])
+            .open("input_examples/attribute.plr")
+            .unwrap();
+        assert_tokens(
+            &mut lexer,
+            &[
+                (Token::Hash, "#"),
+                (Token::ParensOpen, "["),
+                (Token::Identifier, "derive"),
+                (Token::ParensOpen, "("),
+                (Token::Identifier, "Debug"),
+                (Token::ParensClose, ")"),
+                (Token::ParensClose, "]"),
+                (Token::Keyword, "fn"),
+                (Token::Identifier, "main"),
+                (Token::ParensOpen, "("),
+                (Token::ParensClose, ")"),
+            ],
+        );
+
+        // With `#` comments it is still a comment
+        let mut lexer = Lexer::from_source("hash.plr", "#[attr]");
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+    }
+
+    #[test]
+    fn sort_errors_orders_and_dedups() {
+        let unexpected_char = |c, line, column, offset| LexicError::UnexpectedCharacter {
+            c,
+            file: PathBuf::from("errors.plr"),
+            line,
+            column,
+            offset,
+        };
+        let mut errors = vec![
+            unexpected_char('@', 3, 1, 20),
+            unexpected_char('@', 1, 9, 8),
+            unexpected_char('~', 1, 9, 8),
+            unexpected_char('@', 3, 1, 20),
+            unexpected_char('@', 1, 9, 8),
+            unexpected_char('@', 2, 4, 13),
+        ];
+        sort_errors(&mut errors);
+        let found: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            found,
+            [
+                "errors.plr:1:9: Unexpected character '@'",
+                "errors.plr:1:9: Unexpected character '~'",
+                "errors.plr:2:4: Unexpected character '@'",
+                "errors.plr:3:1: Unexpected character '@'",
+            ]
+        );
+    }
+
+    #[test]
+    fn dollar_token() {
+        let mut lexer = Lexer::new("input_examples/dollar.plr").unwrap();
+        assert_tokens(
+            &mut lexer,
+            &[(Token::Dollar, "$"), (Token::Identifier, "name")],
+        );
+    }
+
+    #[test]
+    fn identifier_before_string() {
+        let mut lexer = LexerConfig::default()
+            .adjacent_string_errors(true)
+            .open("input_examples/adjacent_string.plr")
+            .unwrap();
+        let tokenized = lexer.tokenize_all();
+        assert_eq!(
+            tokenized.tokens,
+            [
+                Token::Identifier,
+                Token::StringLiteral,
+                Token::Identifier,
+                Token::StringLiteral
+            ]
+        );
+        assert_eq!(tokenized.ranges[0], 0..5);
+        assert_eq!(tokenized.errors.len(), 1);
+        assert_eq!(
+            tokenized.errors[0].to_string(),
+            "input_examples/adjacent_string.plr:1:6: 'print' is not a string prefix, \
+             expected a separator or an operator before the string"
+        );
+
+        // The error is right between the identifier and the string
+        let mut lexer = LexerConfig::default()
+            .adjacent_string_errors(true)
+            .build("adjacent.plr", "print\"x\"");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "print");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::IdentifierBeforeString { offset: 5, .. })
+        ));
+        assert_eq!(lexer.get_token().unwrap(), Token::StringLiteral);
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+
+        // Only an error when enabled
+        let mut lexer = Lexer::new("input_examples/adjacent_string.plr").unwrap();
+        let tokenized = lexer.tokenize_all();
+        assert_eq!(tokenized.tokens.len(), 4);
+        assert!(tokenized.errors.is_empty());
+    }
+
+    #[test]
+    fn kind_names() {
+        let names = [
+            (Token::Identifier, "identifier"),
+            (Token::Underscore, "underscore"),
+            (Token::Keyword, "keyword"),
+            (Token::NamedLiteral, "named_literal"),
+            (Token::Colon, "colon"),
+            (Token::BlockColon, "block_colon"),
+            (Token::ColonColon, "colon_colon"),
+            (Token::StringLiteral, "string_literal"),
+            (Token::IntegerLiteral, "integer_literal"),
+            (Token::FloatLiteral, "float_literal"),
+            (Token::PercentLiteral, "percent_literal"),
+            (Token::Percent, "percent"),
+            (Token::Plus, "plus"),
+            (Token::Minus, "minus"),
+            (Token::Star, "star"),
+            (Token::Slash, "slash"),
+            (Token::Question, "question"),
+            (Token::Equals, "equals"),
+            (Token::Dot, "dot"),
+            (Token::DotDot, "dot_dot"),
+            (Token::Ellipsis, "ellipsis"),
+            (Token::Pipe, "pipe"),
+            (Token::Ampersand, "ampersand"),
+            (Token::Hash, "hash"),
+            (Token::Dollar, "dollar"),
+            (Token::Caret, "caret"),
+            (Token::Tilde, "tilde"),
+            (Token::InlineCode, "inline_code"),
+            (Token::StringStart, "string_start"),
+            (Token::StringFragment, "string_fragment"),
+            (Token::StringEnd, "string_end"),
+            (Token::InterpolationStart, "interpolation_start"),
+            (Token::InterpolationEnd, "interpolation_end"),
+            (Token::Unit, "unit"),
+            (Token::ParensOpen, "parens_open"),
+            (Token::ParensClose, "parens_close"),
+            (Token::GroupBegin, "group_begin"),
+            (Token::GroupEnd, "group_end"),
+            (Token::Comment, "comment"),
+            (Token::DocComment, "doc_comment"),
+            (Token::LineContinuation, "line_continuation"),
+            (Token::EOF, "eof"),
+        ];
+        for (tok, name) in names {
+            assert_eq!(tok.kind_name(), name);
+        }
+    }
+
+    #[test]
+    fn token_at() {
+        let code = "let name = @ value";
+        let mut lexer = Lexer::from_source("complete.plr", code);
+        assert_eq!(lexer.token_at(6), Some((Token::Identifier, 4..8)));
+        // Past an error, and in order
+        assert_eq!(lexer.token_at(14), Some((Token::Identifier, 13..18)));
+        assert_eq!(lexer.token_at(18), Some((Token::Identifier, 13..18)));
+
+        let mut lexer = Lexer::from_source("complete.plr", "a  b");
+        assert_eq!(lexer.token_at(2), None);
+        assert_eq!(Lexer::from_source("complete.plr", code).token_at(100), None);
+
+        // The word being typed, at the end or in the middle of the source
+        let mut lexer = Lexer::from_source("complete.plr", "let va");
+        assert_eq!(lexer.token_at(6), Some((Token::Identifier, 4..6)));
+        let mut lexer = Lexer::from_source("complete.plr", "let va\nother");
+        assert_eq!(lexer.token_at(3), Some((Token::Keyword, 0..3)));
+        assert_eq!(lexer.token_at(6), Some((Token::Identifier, 4..6)));
+        // Right between two tokens, the one after is under the offset
+        let mut lexer = Lexer::from_source("complete.plr", "call(x)");
+        assert_eq!(lexer.token_at(4), Some((Token::ParensOpen, 4..5)));
+    }
+
+    #[test]
+    fn empty_lexer() {
+        for mut lexer in [Lexer::empty(), Lexer::default()] {
+            assert_eq!(lexer.input_filepath(), Path::new("<empty>"));
+            assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+            assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+            assert!(lexer.is_at_eof());
+        }
+    }
+
+    #[test]
+    fn highlight_groups() {
+        let groups = [
+            (Token::Identifier, HighlightGroup::Identifier),
+            (Token::Keyword, HighlightGroup::Keyword),
+            (Token::StringLiteral, HighlightGroup::String),
+            (Token::StringFragment, HighlightGroup::String),
+            (Token::FloatLiteral, HighlightGroup::Number),
+            (Token::DocComment, HighlightGroup::Comment),
+            (Token::Plus, HighlightGroup::Operator),
+            (Token::Equals, HighlightGroup::Operator),
+            (Token::ParensOpen, HighlightGroup::Punctuation),
+            (Token::ColonColon, HighlightGroup::Punctuation),
+            (Token::GroupBegin, HighlightGroup::None),
+            (Token::EOF, HighlightGroup::None),
+        ];
+        for (tok, group) in groups {
+            assert_eq!(tok.highlight_group(), group, "{:?}", tok);
+        }
+    }
+
+    #[test]
+    fn named_literals() {
+        let code = "null nil if";
+        let mut lexer = LexerConfig::default()
+            .named_literals(&["null", "if"])
+            .build("literals.plr", code);
+        assert_eq!(lexer.get_token().unwrap(), Token::NamedLiteral);
+        assert_eq!(lexer.token_str(), "null");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        // Taken before keywords
+        assert_eq!(lexer.get_token().unwrap(), Token::NamedLiteral);
+        assert_eq!(lexer.token_str(), "if");
+
+        // None by default
+        let tokens = Lexer::from_source("literals.plr", code)
+            .tokenize_all()
+            .tokens;
+        assert_eq!(
+            tokens,
+            [Token::Identifier, Token::Identifier, Token::Keyword]
+        );
+    }
+
+    #[test]
+    fn rewind_token() {
+        let mut lexer = Lexer::from_source("rewind.plr", "first (second)");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.peek_token().unwrap(), Token::ParensOpen);
+        lexer.rewind_token();
+        // Only once
+        lexer.rewind_token();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "first");
+        assert_eq!(lexer.get_token().unwrap(), Token::ParensOpen);
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        lexer.rewind_token();
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert_eq!(lexer.token_str(), "second");
+        assert_eq!(lexer.token_span(), Span::from(7..13));
+        assert_eq!(lexer.get_token().unwrap(), Token::ParensClose);
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+    }
+
+    #[test]
+    fn from_static() {
+        const CODE: &str = "static (code)";
+        let lexemes: Vec<&'static str> = {
+            let mut lexer = Lexer::from_static("static.plr", CODE);
+            let mut lexemes = Vec::new();
+            while lexer.get_token().unwrap() != Token::EOF {
+                lexemes.push(lexer.static_token_str().unwrap());
+            }
+            lexemes
+        };
+        assert_eq!(lexemes, ["static", "(", "code", ")"]);
+        // Borrowed, not copied
+        assert!(std::ptr::eq(lexemes[0].as_ptr(), CODE.as_ptr()));
+
+        // Editing copies the source out
+        let mut lexer = Lexer::from_static("static.plr", CODE);
+        lexer.relex_line(1, "edited").unwrap();
+        assert_eq!(lexer.token_str(), "edited");
+        assert_eq!(lexer.static_token_str(), None);
+        assert_eq!(
+            Lexer::from_source("owned.plr", CODE).static_token_str(),
+            None
+        );
+    }
+
+    #[test]
+    fn max_string_len() {
+        let code = "\"short\" \"much \\\" too long\" after \"exactly\"";
+        let mut lexer = LexerConfig::default()
+            .max_string_len(9)
+            .build("strings.plr", code);
+        let tokenized = lexer.tokenize_all();
+        assert_eq!(
+            tokenized.tokens,
+            [
+                Token::StringLiteral,
+                Token::Identifier,
+                Token::StringLiteral
+            ]
+        );
+        assert!(matches!(
+            tokenized.errors[..],
+            [LexicError::StringTooLong {
+                line: 1,
+                column: 9,
+                offset: 8,
+                max: 9,
+                ..
+            }]
+        ));
+        assert_eq!(
+            tokenized.errors[0].to_string(),
+            "strings.plr:1:9: String longer than 9 bytes"
+        );
+
+        // Found before the end of the string, even if it has none
+        let mut lexer = LexerConfig::default()
+            .max_string_len(4)
+            .build("strings.plr", "\"unterminated");
+        assert!(matches!(
+            lexer.get_token(),
+            Err(LexicError::StringTooLong { .. })
+        ));
+        assert_eq!(lexer.bytes_consumed(), 4);
+        assert_eq!(lexer.get_token().unwrap(), Token::EOF);
+
+        // Unlimited by default
+        let tokenized = Lexer::from_source("strings.plr", code).tokenize_all();
+        assert!(tokenized.errors.is_empty());
+    }
+
+    #[test]
+    fn as_tokens_with_text() {
+        let path = "input_examples/simple1.plr";
+        let mut lexer = Lexer::new(path).unwrap();
+        let mut expected = Vec::new();
+        loop {
+            match lexer.get_token().unwrap() {
+                Token::EOF => break,
+                tok => expected.push((tok, lexer.token_str().to_owned())),
+            }
+        }
+        let tokens = Lexer::new(path).unwrap().as_tokens_with_text().unwrap();
+        assert_eq!(tokens, expected);
+
+        let result = Lexer::from_source("text.plr", "a @ b").as_tokens_with_text();
+        assert!(matches!(
+            result,
+            Err(LexicError::UnexpectedCharacter { c: '@', .. })
+        ));
+    }
+
+    #[test]
+    fn caret_and_tilde() {
+        let mut lexer = Lexer::new("input_examples/caret_tilde.plr").unwrap();
+        assert_tokens(
+            &mut lexer,
+            &[
+                (Token::Tilde, "~"),
+                (Token::Identifier, "a"),
+                (Token::Caret, "^"),
+                (Token::Identifier, "b"),
+            ],
+        );
+    }
+
+    #[test]
+    fn char_ranges() {
+        let code = "ação (ü) x";
+        let mut lexer = LexerConfig::default()
+            .char_ranges(true)
+            .build("chars.plr", code);
+        let tokenized = lexer.tokenize_all();
+        assert_eq!(tokenized.ranges, [0..6, 7..8, 8..10, 10..11, 12..13]);
+        assert_eq!(tokenized.char_ranges, [0..4, 5..6, 6..7, 7..8, 9..10]);
+
+        // Not computed by default
+        let tokenized = Lexer::from_source("chars.plr", code).tokenize_all();
+        assert_eq!(tokenized.ranges.len(), 5);
+        assert!(tokenized.char_ranges.is_empty());
+    }
+
+    #[test]
+    fn error_codes() {
+        let error = Lexer::from_source("codes.plr", "@")
+            .get_token()
+            .unwrap_err();
+        assert_eq!(error.code(), "PLR0001");
+        assert!(LexicError::explain(error.code())
+            .unwrap()
+            .starts_with("A character that starts no token"));
+        assert_eq!(LexicError::explain("PLR9999"), None);
+        for (i, (code, _)) in EXPLANATIONS.iter().enumerate() {
+            assert_eq!(*code, format!("PLR{:04}", i + 1));
+        }
+    }
+
+    #[test]
+    fn tokenize_all_with_capacity() {
+        let code = read_fixture("input_examples/broken_many.plr");
+        let messages = |errors: &[LexicError]| -> Vec<String> {
+            errors.iter().map(|e| e.to_string()).collect()
+        };
+        let expected = Lexer::from_source("capacity.plr", &code).tokenize_all();
+        for hint in [0, 1, code.len() / 4, code.len() * 2] {
+            let tokenized =
+                Lexer::from_source("capacity.plr", &code).tokenize_all_with_capacity(hint);
+            assert_eq!(tokenized.tokens, expected.tokens);
+            assert_eq!(tokenized.ranges, expected.ranges);
+            assert_eq!(messages(&tokenized.errors), messages(&expected.errors));
+            assert!(tokenized.tokens.capacity() >= hint);
+        }
+
+        let tokenized = LexerConfig::default()
+            .preallocate_tokens(true)
+            .build("capacity.plr", &code)
+            .tokenize_all();
+        assert_eq!(tokenized.tokens, expected.tokens);
+        assert!(tokenized.ranges.capacity() >= code.len() / LexerConfig::BYTES_PER_TOKEN_HINT);
+    }
+
+    #[test]
+    fn numeric_value_of_other_tokens() {
+        let mut lexer = Lexer::from_source("numbers.plr", "name 1.5");
+        assert_eq!(lexer.get_token().unwrap(), Token::Identifier);
+        assert!(matches!(
+            lexer.numeric_value::<u64>(),
+            Err(LexicError::UnexpectedToken {
+                expected: Token::IntegerLiteral,
+                found: Token::Identifier,
+                line: 1,
+                column: 1,
+                ..
+            })
+        ));
+        assert_eq!(lexer.get_token().unwrap(), Token::FloatLiteral);
+        assert!(lexer.numeric_value::<u64>().is_err());
+    }
+
+    #[test]
+    fn error_code_examples() {
+        for &(code, explanation) in EXPLANATIONS {
+            let Some((_, example)) = explanation.split_once("For example:\n\n") else {
+                continue;
+            };
+            let example: Vec<_> = example
+                .lines()
+                .map_while(|line| line.strip_prefix("    "))
+                .collect();
+            let example = example.join("\n");
+            let config = match code {
+                "PLR0012" => LexerConfig::default().inline_code(true),
+                "PLR0014" | "PLR0015" | "PLR0016" => LexerConfig::default().directives(true),
+                "PLR0018" => LexerConfig::default().group_delimiters(&[('(', ')'), ('[', ']')]),
+                "PLR0019" => LexerConfig::default().adjacent_string_errors(true),
+                _ => LexerConfig::default(),
+            };
+            let mut lexer = config.build("example.plr", example.as_str());
+            let mut errors = lexer.tokenize_all().errors;
+            if code == "PLR0010" {
+                // Only found when asking for the value
+                let mut lexer = Lexer::from_source("example.plr", example.as_str());
+                while lexer.get_token().unwrap() != Token::IntegerLiteral {}
+                errors.extend(lexer.numeric_value::<u64>().err());
             }
+            let codes: Vec<_> = errors.iter().map(LexicError::code).collect();
+            assert_eq!(codes, [code], "{:?}", example);
         }
     }
 }
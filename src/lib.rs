@@ -1 +1,10 @@
+pub mod dump;
 pub mod lexer;
+
+// The old identation based lexer, kept around but only built for its tests
+#[cfg(test)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
+mod _lexer;
+
+#[cfg(test)]
+mod test_support;
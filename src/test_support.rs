@@ -0,0 +1,56 @@
+//! Helpers shared by the unit tests
+
+use crate::lexer::{Lexer, Token};
+
+/// Compares two token lists, describing the first difference between them
+/// when they don't match. Easier to read than an `assert_eq!` of two long
+/// lists when only one token changed.
+pub fn compare_tokens(expected: &[(Token, &str)], actual: &[(Token, &str)]) -> Result<(), String> {
+    let mismatch = expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual);
+    match mismatch {
+        Some(index) => Err(format!(
+            "Token {} differs, expected {:?} {:?} but got {:?} {:?}",
+            index, expected[index].0, expected[index].1, actual[index].0, actual[index].1
+        )),
+        None if expected.len() != actual.len() => Err(format!(
+            "Expected {} tokens but got {}, the first {} match",
+            expected.len(),
+            actual.len(),
+            expected.len().min(actual.len())
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Lexes the rest of the source and asserts its tokens and their text are
+/// the expected ones, panicking with the first difference or error.
+#[track_caller]
+pub fn assert_tokens(lexer: &mut Lexer, expected: &[(Token, &str)]) {
+    let tokens = lexer.as_tokens_with_text().unwrap();
+    let tokens: Vec<_> = tokens.iter().map(|(tok, s)| (*tok, s.as_str())).collect();
+    if let Err(difference) = compare_tokens(expected, &tokens) {
+        panic!("{}", difference);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_tokens_mismatch() {
+        let expected = [(Token::Identifier, "a"), (Token::Colon, ":")];
+        assert_eq!(compare_tokens(&expected, &expected), Ok(()));
+        assert_eq!(
+            compare_tokens(&expected, &[(Token::Identifier, "a"), (Token::Equals, "=")]),
+            Err(r#"Token 1 differs, expected Colon ":" but got Equals "=""#.to_owned())
+        );
+        assert_eq!(
+            compare_tokens(&expected, &expected[..1]),
+            Err("Expected 2 tokens but got 1, the first 1 match".to_owned())
+        );
+    }
+}
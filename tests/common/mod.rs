@@ -0,0 +1,16 @@
+//! Inputs shared by the integration tests and the benchmarks
+
+/// A single line of about `size` bytes, with no line breaks, made of
+/// identifiers and parens.
+pub fn single_long_line(size: usize) -> String {
+    let words = ["alpha", "beta(", "gamma)", "delta:", "_epsilon1", "zeta"];
+    let mut code = String::with_capacity(size + 16);
+    for word in words.iter().cycle() {
+        if code.len() >= size {
+            break;
+        }
+        code.push_str(word);
+        code.push(' ');
+    }
+    code
+}
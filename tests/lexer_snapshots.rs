@@ -0,0 +1,80 @@
+//! Snapshots of the tokens of the files in `input_examples`. After changing
+//! the lexer, review the differences with `cargo insta review`.
+
+use pilaris::lexer::{Lexer, LexerConfig, Token};
+use std::fmt::Write;
+
+/// Every token with its position and text, one per line, until the end of
+/// the input or the first error.
+fn dump(mut lexer: Lexer) -> String {
+    let mut dump = String::new();
+    loop {
+        match lexer.get_token() {
+            Ok(tok) => {
+                let (line, column) = lexer.line_col_at(lexer.token_span().start);
+                writeln!(
+                    dump,
+                    "{:?} {}:{} {:?}",
+                    tok,
+                    line,
+                    column,
+                    lexer.token_str()
+                )
+                .unwrap();
+                if tok == Token::EOF {
+                    break dump;
+                }
+            }
+            Err(e) => {
+                writeln!(dump, "error: {}", e.display_normalized()).unwrap();
+                break dump;
+            }
+        }
+    }
+}
+
+#[test]
+fn simple1() {
+    insta::assert_snapshot!(dump(Lexer::new("input_examples/simple1.plr").unwrap()));
+}
+
+#[test]
+fn group_markers() {
+    let config = LexerConfig::default().emit_group_markers(true);
+    insta::assert_snapshot!(dump(config.open("input_examples/simple1.plr").unwrap()));
+}
+
+#[test]
+fn comments() {
+    let config = LexerConfig::default().preserve_trivia(true);
+    insta::assert_snapshot!(dump(config.open("input_examples/comments.plr").unwrap()));
+}
+
+#[test]
+fn block_colon() {
+    let config = LexerConfig::default().block_colons(true);
+    insta::assert_snapshot!(dump(config.open("input_examples/block_colon.plr").unwrap()));
+}
+
+#[test]
+fn numbers() {
+    insta::assert_snapshot!(dump(Lexer::new("input_examples/percent.plr").unwrap()));
+}
+
+#[test]
+fn interpolation() {
+    let config = LexerConfig::default().string_interpolation(true);
+    insta::assert_snapshot!(dump(
+        config.open("input_examples/interpolation.plr").unwrap()
+    ));
+}
+
+#[test]
+fn punctuation() {
+    insta::assert_snapshot!(dump(Lexer::new("input_examples/punctuation.plr").unwrap()));
+}
+
+#[test]
+fn broken() {
+    insta::assert_snapshot!(dump(Lexer::new("input_examples/broken1.plr").unwrap()));
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use pilaris::lexer::{Lexer, Token};
+use std::time::{Duration, Instant};
+
+#[test]
+fn long_line_is_linear() {
+    // Small enough for a debug build, a quadratic lexer would still take
+    // minutes on it
+    const SIZE: usize = 1024 * 1024;
+    let code = common::single_long_line(SIZE);
+    let mut lexer = Lexer::from_source("long_line.plr", code);
+
+    let start = Instant::now();
+    let mut token_count = 0;
+    while lexer.get_token().unwrap() != Token::EOF {
+        token_count += 1;
+    }
+    let elapsed = start.elapsed();
+
+    assert!(token_count > SIZE / 10);
+    assert!(
+        elapsed < Duration::from_secs(30),
+        "Lexing a 1MB line took {:?}",
+        elapsed
+    );
+}
@@ -0,0 +1,275 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn pilarisc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pilarisc"))
+        .args(args)
+        .output()
+        .expect("Failed to run pilarisc")
+}
+
+#[test]
+fn check_clean_file() {
+    let output = pilarisc(&["--check", "input_examples/simple1.plr"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn check_broken_file() {
+    let output = pilarisc(&["--check", "input_examples/broken1.plr"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.trim_end(),
+        "input_examples/broken1.plr:2:7: Unexpected character '@'"
+    );
+}
+
+#[test]
+fn repl_session() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pilarisc"))
+        .arg("--repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run pilarisc");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"hello(world)\nbad @\n:quit\nnever lexed\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "> Identifier \"hello\", starts at col: 0\n\
+         ParensOpen \"(\", starts at col: 5\n\
+         Identifier \"world\", starts at col: 6\n\
+         ParensClose \")\", starts at col: 11\n\
+         EOF \"\", starts at col: 12\n\
+         > Identifier \"bad\", starts at col: 0\n\
+         > "
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "<repl>:1:5: Unexpected character '@'\n");
+}
+
+#[test]
+fn json_error_format() {
+    let output = pilarisc(&[
+        "--check",
+        "--error-format",
+        "json",
+        "input_examples/broken1.plr",
+    ]);
+    assert!(!output.status.success());
+    let errors: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    let errors = errors.as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    let error = &errors[0];
+    assert_eq!(error["file"], "input_examples/broken1.plr");
+    assert_eq!(error["line"], 2);
+    assert_eq!(error["column"], 7);
+    assert_eq!(error["offset"], 19);
+    assert_eq!(error["message"], "Unexpected character '@'");
+    assert_eq!(error["code"], "PLR0001");
+    assert_eq!(error["severity"], "error");
+
+    // Even when the source can't be read
+    let output = pilarisc(&[
+        "--check",
+        "--error-format",
+        "json",
+        "input_examples/nonexistent.plr",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let errors: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(errors[0]["code"], "PLR0013");
+}
+
+#[test]
+fn explain_code() {
+    let output = pilarisc(&["--explain", "PLR0001"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("A character that starts no token"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("For example:"), "{}", stdout);
+
+    let output = pilarisc(&["--explain", "PLR9999"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "Unknown error code 'PLR9999'\n");
+}
+
+#[test]
+fn check_max_errors() {
+    let output = pilarisc(&["--check", "input_examples/broken_many.plr"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().count(), 5);
+
+    let output = pilarisc(&[
+        "--check",
+        "--max-errors",
+        "2",
+        "input_examples/broken_many.plr",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        "input_examples/broken_many.plr:1:5: Unexpected character '@'\n\
+         input_examples/broken_many.plr:1:11: Unexpected character ';'\n\
+         input_examples/broken_many.plr:2:7: Too many errors, stopped after 2\n"
+    );
+}
+
+#[test]
+fn invalid_config() {
+    let output = pilarisc(&["--check", "--max-errors", "0", "input_examples/simple1.plr"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("max_errors is 0"), "{}", stderr);
+}
+
+#[test]
+fn token_columns_snapshot() {
+    let output = pilarisc(&[
+        "--tokens",
+        "--format",
+        "columns",
+        "input_examples/simple1.plr",
+    ]);
+    assert!(output.status.success());
+    let expected =
+        std::fs::read_to_string("util_files/test_data/lexer_output/simple1.plr.columns.txt")
+            .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn emit_tokens() {
+    let path = std::env::temp_dir().join(format!("pilarisc-emit-{}.json", std::process::id()));
+    let emit = format!("tokens={}", path.display());
+    let output = pilarisc(&["--emit", &emit, "input_examples/simple1.plr"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let tokens: serde_json::Value = serde_json::from_str(&content).unwrap();
+    let tokens = tokens.as_array().unwrap();
+    assert_eq!(tokens.len(), 30);
+    assert_eq!(
+        tokens[0],
+        serde_json::json!({
+            "kind": "identifier",
+            "lexeme": "ファントム",
+            "line": 1,
+            "column": 1,
+            "start": 0,
+            "end": 15,
+        })
+    );
+    assert_eq!(tokens[29]["lexeme"], "_1234");
+
+    let output = pilarisc(&["--emit", "ast=out.json", "input_examples/simple1.plr"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn defined_flags() {
+    let identifiers = |output: Output| -> Vec<String> {
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("Identifier"))
+            .map(|line| line.split('"').nth(1).unwrap().to_owned())
+            .collect()
+    };
+    let output = pilarisc(&["--tokens", "--directives", "input_examples/conditional.plr"]);
+    assert_eq!(identifiers(output), ["always", "after"]);
+    let output = pilarisc(&["--tokens", "-D", "DEBUG", "input_examples/conditional.plr"]);
+    assert_eq!(identifiers(output), ["always", "debug_only", "after"]);
+
+    // Without directives, `#if` lines are plain comments
+    let output = pilarisc(&["--tokens", "input_examples/conditional.plr"]);
+    assert_eq!(
+        identifiers(output),
+        ["always", "debug_only", "verbose_only", "after"]
+    );
+    let output = pilarisc(&["--check", "input_examples/if_comment.plr"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn time_flag() {
+    let is_timing =
+        |line: &str| line.starts_with("Lexed 30 tokens in ") && line.ends_with(" tokens/s)");
+
+    let output = pilarisc(&["--time", "input_examples/simple1.plr"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(is_timing(stderr.trim_end()), "{}", stderr);
+
+    // Along with the dump, which is not timed
+    let output = pilarisc(&["--tokens", "--time", "input_examples/simple1.plr"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 31);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(is_timing(stderr.trim_end()), "{}", stderr);
+
+    // Along with emitting, which still happens
+    let path = std::env::temp_dir().join(format!("pilarisc-time-{}.json", std::process::id()));
+    let emit = format!("tokens={}", path.display());
+    let output = pilarisc(&["--time", "--emit", &emit, "input_examples/simple1.plr"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(is_timing(stderr.trim_end()), "{}", stderr);
+    assert!(path.exists());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "watch")]
+#[test]
+#[ignore = "waits for file system events"]
+fn watch_relexes() {
+    use std::io::{BufRead, BufReader};
+
+    let path = std::env::temp_dir().join(format!("pilarisc-watch-{}.plr", std::process::id()));
+    std::fs::write(&path, "a @\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pilarisc"))
+        .arg("--watch")
+        .arg(&path)
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run pilarisc");
+    let mut lines = BufReader::new(child.stderr.take().unwrap()).lines();
+    let mut next_line = || lines.next().unwrap().unwrap();
+    let waiting = format!("Waiting for changes to {}", path.display());
+
+    assert!(next_line().ends_with("Unexpected character '@'"));
+    assert_eq!(next_line(), waiting);
+    std::fs::write(&path, "a b\n").unwrap();
+    // Lexed again, without errors this time
+    assert_eq!(next_line(), waiting);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}